@@ -0,0 +1,150 @@
+//! Command line tool for serf configuration files.
+//!
+//! ```text
+//! serf-config [--config <path>] validate
+//! serf-config [--config <path>] export <game> <file> [--author <name>]
+//! serf-config [--config <path>] import <file> [--replace | --rename]
+//! serf-config convert <from> <to>
+//! ```
+//!
+//! Files are read and written as TOML for `.toml`, YAML for `.yaml` or `.yml`,
+//! and JSON otherwise.
+
+use anyhow::{bail, Context, Result};
+use common::{Configuration, OnConflict, Profile, Severity};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: serf-config [--config <path>] <command>
+
+commands:
+    validate                                  report problems with the configuration
+    export <game> <file> [--author <name>]    write one game out as a profile
+    import <file> [--replace | --rename]      add the game from a profile
+    convert <from> <to>                       rewrite a configuration in another format";
+
+#[derive(Default)]
+struct Options {
+    config: Option<PathBuf>,
+    author: String,
+    on_conflict: Option<OnConflict>,
+    command: Vec<String>,
+}
+
+fn main() -> ExitCode {
+    match run(std::env::args().skip(1).collect()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("serf-config: {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn options(args: Vec<String>) -> Result<Options> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| match args.next() {
+            Some(value) => Ok(value),
+            None => Err(anyhow::anyhow!("{} needs a value\n{}", name, USAGE)),
+        };
+        if arg == "--config" {
+            options.config = Some(PathBuf::from(value("--config")?));
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            options.config = Some(PathBuf::from(path));
+        } else if arg == "--author" {
+            options.author = value("--author")?;
+        } else if let Some(author) = arg.strip_prefix("--author=") {
+            options.author = author.to_string();
+        } else if arg == "--replace" {
+            options.on_conflict = Some(OnConflict::Replace);
+        } else if arg == "--rename" {
+            options.on_conflict = Some(OnConflict::Rename);
+        } else {
+            options.command.push(arg);
+        }
+    }
+    Ok(options)
+}
+
+fn run(args: Vec<String>) -> Result<ExitCode> {
+    let options = options(args)?;
+    // Looked for only by the commands using it, as finding it may first copy
+    // a legacy configuration into the per-user directory.
+    let path = || Configuration::locate(options.config.clone());
+
+    match options
+        .command
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()[..]
+    {
+        ["validate"] => validate(&path()?),
+        ["export", game, file] => export(&path()?, game, Path::new(file), &options.author),
+        ["import", file] => import(
+            &path()?,
+            Path::new(file),
+            options.on_conflict.unwrap_or(OnConflict::Refuse),
+        ),
+        ["convert", from, to] => convert(Path::new(from), Path::new(to)),
+        _ => bail!(USAGE),
+    }
+}
+
+// Print every problem with the configuration, failing if any is an error.
+fn validate(path: &Path) -> Result<ExitCode> {
+    let configuration = Configuration::inspect(path)
+        .with_context(|| format!("Unable to read {}", path.display()))?;
+    let diagnostics = common::validate(&configuration);
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic);
+    }
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        return Ok(ExitCode::FAILURE);
+    }
+    println!("{}: ok", path.display());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn export(path: &Path, game: &str, file: &Path, author: &str) -> Result<ExitCode> {
+    let configuration = Configuration::inspect(path)
+        .with_context(|| format!("Unable to read {}", path.display()))?;
+    let Some(ix) = configuration.game_named(game) else {
+        bail!("There is no game called '{}'", game);
+    };
+    Profile::new(&configuration.games[ix], author)
+        .write(file)
+        .with_context(|| format!("Unable to write {}", file.display()))?;
+    println!("Exported '{}' to {}", game, file.display());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn import(path: &Path, file: &Path, on_conflict: OnConflict) -> Result<ExitCode> {
+    let profile =
+        Profile::read(file).with_context(|| format!("Unable to read {}", file.display()))?;
+    let mut configuration = Configuration::load(path)?;
+    let ix = configuration
+        .import(profile, on_conflict)
+        .map_err(|e| anyhow::anyhow!("{}; use --replace or --rename to import it anyway", e))?;
+    configuration.save()?;
+    println!(
+        "Imported '{}' into {}",
+        configuration.games[ix].name,
+        path.display()
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+// Write the configuration at `from` to `to`, in the format `to` names. The
+// configuration need not be valid, so a broken one can be fixed in another
+// format.
+fn convert(from: &Path, to: &Path) -> Result<ExitCode> {
+    let mut configuration = Configuration::inspect(from)
+        .with_context(|| format!("Unable to read {}", from.display()))?;
+    configuration
+        .save_as(to)
+        .with_context(|| format!("Unable to write {}", to.display()))?;
+    println!("Converted {} to {}", from.display(), to.display());
+    Ok(ExitCode::SUCCESS)
+}
@@ -1,108 +1,603 @@
 use crate::vkey::code_for_label;
-use anyhow::Result;
+use crate::Format;
+use anyhow::{anyhow, bail, Result};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
-#[derive(Serialize, Deserialize, Default)]
+/// Environment variable naming the configuration file, for when `--config`
+/// is not given.
+pub const CONFIG_ENV: &str = "SERF_CONFIG";
+
+/// The configuration layout this build reads and writes. Bump it, and add a
+/// step to `MIGRATIONS`, whenever a change would stop older files parsing.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// How many earlier saves to keep, as `configuration.json.1.bak` (the most
+/// recent) to `configuration.json.5.bak`.
+pub const BACKUPS: usize = 5;
+
+// `MIGRATIONS[n]` upgrades a version `n` configuration to version `n + 1`.
+const MIGRATIONS: [fn(&mut Value) -> Result<()>; 1] = [from_unversioned];
+
+#[derive(Serialize, Deserialize)]
 pub struct Configuration {
+    pub version: u32,
     pub games: Vec<Game>,
+    // Where the configuration was loaded from, and so where it is saved.
+    #[serde(skip)]
+    path: PathBuf,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+impl Default for Configuration {
+    fn default() -> Self {
+        Configuration {
+            version: CONFIG_VERSION,
+            games: vec![],
+            path: PathBuf::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 pub struct Game {
     pub name: String,
+    /// The game this one inherits its controls from, by name. Only the fields
+    /// in `overrides` are its own; the rest follow the parent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
     pub controls: crate::ButtonMapping,
+    /// Fields of `controls`, by name, set by this game rather than inherited.
+    /// They are the fields an inheriting game's stored `controls` hold.
+    #[serde(skip)]
+    pub overrides: BTreeSet<String>,
 }
 
 impl Default for Game {
     fn default() -> Self {
         Game {
             name: "Unnamed mapping".into(),
-            controls: crate::ButtonMapping::default(),
+            parent: None,
+            controls: crate::ButtonMapping::starter(),
+            overrides: BTreeSet::new(),
         }
     }
 }
 
-impl Configuration {
-    pub fn save(&self) -> Result<()> {
-        // Try to write to the matching location we read from (or initially created).
-        // Try finding a configuration.json in the executable dir
-        let mut edir = std::env::current_exe()?;
-        edir.pop();
-        edir.push("configuration.json");
-        if edir.exists() {
-            std::fs::write(edir, serde_json::to_string_pretty(self)?)?;
+impl crate::ButtonMapping {
+    /// The mapping new configurations and games start from.
+    pub fn starter() -> Self {
+        crate::ButtonMapping {
+            dpadl: code_for_label("Left Arrow"),
+            dpadr: code_for_label("Right Arrow"),
+            dpadu: code_for_label("Up Arrow"),
+            dpadd: code_for_label("Down Arrow"),
+            lsticku: code_for_label("W"),
+            lstickd: code_for_label("S"),
+            lstickr: code_for_label("A"),
+            lstickl: code_for_label("D"),
+            buttona: code_for_label("Spacebar"),
+            buttonb: code_for_label("Left Control"),
+            buttonx: code_for_label("F"),
+            buttony: code_for_label("1"),
+            start: code_for_label("Escape"),
+            shoulderl: code_for_label("Q"),
+            shoulderr: code_for_label("E"),
+            lthumb: code_for_label("Shift"),
+            rthumb: code_for_label("V"),
+            back: code_for_label("Tab"),
+            left_fire_mode: crate::FireMode::Hold,
+            right_fire_mode: crate::FireMode::Hold,
+            movement_multiplier: 2000,
+            sampling_interval: 2000,
+            recoil_compensation_active: false,
+            recoil_sideways_compensation: 0,
+            recoil_vertical_compensation: 0,
+            recoil_impulse_vertical: 0,
+            recoil_impulse_duration: 0,
+            recoil_activation: crate::RecoilActivation::WhileAiming,
+            recoil_modifier: 0,
+            stick_gate: crate::StickGate::Circle,
+            socd_horizontal: crate::SocdMode::LastInputWins,
+            socd_vertical: crate::SocdMode::LastInputWins,
+            walk: code_for_label("Z"),
+            walk_speed: 50,
+            movement_ramp: 0,
+            left_autofire_rpm: 800,
+            left_autofire_duty: 50,
+            right_autofire_rpm: 800,
+            right_autofire_duty: 50,
+            left_burst_count: 3,
+            right_burst_count: 3,
+            turbo: crate::TurboRates::default(),
+            activation: crate::Activations::default(),
+            recoil_pattern: vec![],
+            recoil_pattern_per_shot: false,
+            weapons: vec![],
+            active_weapon: None,
+            weapon_wheel: false,
+            macros: vec![],
+            chords: vec![],
+            layers: vec![],
+            script: String::new(),
         }
+    }
+}
 
-        // Otherwise try to current working directory
-        let mut cdir = std::env::current_dir()?;
-        cdir.push("configuration.json");
-        if cdir.exists() {
-            std::fs::write(cdir, serde_json::to_string_pretty(self)?)?;
+impl Configuration {
+    /// Decide where the configuration lives: the `--config` path if one was
+    /// given, then `SERF_CONFIG`, then `serf/configuration.json` in the
+    /// per-user configuration directory (`%APPDATA%`, or `$XDG_CONFIG_HOME`
+    /// falling back to `~/.config`).
+    ///
+    /// Configurations used to live next to the executable or in the working
+    /// directory; the first time the per-user location is used, such a file
+    /// is copied there.
+    pub fn locate(flag: Option<PathBuf>) -> Result<PathBuf> {
+        if let Some(path) = choose(flag, std::env::var_os(CONFIG_ENV)) {
+            return Ok(path);
+        }
+        let path = user_config_path()?;
+        if !path.exists() {
+            let mut edir = std::env::current_exe()?;
+            edir.pop();
+            let cdir = std::env::current_dir()?;
+            let legacy = [edir, cdir]
+                .into_iter()
+                .map(|dir| dir.join("configuration.json"))
+                .find(|legacy| legacy.exists());
+            if let Some(legacy) = legacy {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::copy(legacy, &path)?;
+            }
         }
+        Ok(path)
+    }
 
-        Ok(())
+    /// Where the configuration was loaded from, and will be saved to.
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
-    pub fn load() -> Result<Self> {
-        // Try finding a configuration.json in the executable dir
-        let mut edir = std::env::current_exe()?;
-        edir.pop();
-        edir.push("configuration.json");
-        if edir.exists() {
-            return Ok(serde_json::from_slice::<Configuration>(&std::fs::read(
-                edir,
-            )?)?);
+    /// Save over the file the configuration came from, in the format its
+    /// extension calls for, keeping the previous contents as the newest
+    /// backup. The new contents are written to a
+    /// temporary file and renamed into place, so a crash part way through
+    /// leaves the old file intact.
+    pub fn save(&self) -> Result<()> {
+        let contents =
+            Format::from_path(&self.path).to_string(&crate::inheritance::stored(self)?)?;
+        if self.path.exists() {
+            for n in (1..BACKUPS).rev() {
+                let from = backup_path(&self.path, n);
+                if from.exists() {
+                    std::fs::rename(from, backup_path(&self.path, n + 1))?;
+                }
+            }
+            std::fs::copy(&self.path, backup_path(&self.path, 1))?;
         }
+        write_atomic(&self.path, contents.as_bytes())
+    }
+
+    /// Save to `path` from now on, converting to the format its extension
+    /// calls for.
+    pub fn save_as(&mut self, path: &Path) -> Result<()> {
+        self.path = path.to_path_buf();
+        self.save()
+    }
 
-        // Try finding a configuration.json in the current directory
-        let mut cdir = std::env::current_dir()?;
-        cdir.push("configuration.json");
-        if cdir.exists() {
-            return Ok(serde_json::from_slice::<Configuration>(&std::fs::read(
-                cdir,
-            )?)?);
+    /// The most recent backup of the configuration at `path` which can still
+    /// be read, if any.
+    pub fn newest_valid_backup(path: &Path) -> Option<PathBuf> {
+        (1..=BACKUPS).map(|n| backup_path(path, n)).find(|backup| {
+            std::fs::read(backup).is_ok_and(|bytes| parse(&bytes, Format::from_path(path)).is_ok())
+        })
+    }
+
+    /// Replace the configuration at `path` with `backup` and load it. The
+    /// unreadable file is kept alongside, with `.broken` appended.
+    pub fn restore(path: &Path, backup: &Path) -> Result<Self> {
+        let bytes = std::fs::read(backup)?;
+        parse(&bytes, Format::from_path(path))?;
+        if path.exists() {
+            std::fs::copy(path, sibling(path, "broken"))?;
         }
+        write_atomic(path, &bytes)?;
+        Configuration::read(path)
+    }
 
-        // Try to generate a brand new configuration in the executable dir.
-        //let cfg = Configuration::default();
+    /// Load the configuration at `path`, creating a starter configuration
+    /// there if there is none yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        if path.exists() {
+            return Configuration::read(path);
+        }
 
         let games = vec![Game {
             name: "CoD Mediocre Warfare".into(),
-            controls: {
-                crate::ButtonMapping {
-                    dpadl: code_for_label("Left Arrow"),
-                    dpadr: code_for_label("Right Arrow"),
-                    dpadu: code_for_label("Up Arrow"),
-                    dpadd: code_for_label("Down Arrow"),
-                    lsticku: code_for_label("W"),
-                    lstickd: code_for_label("S"),
-                    lstickr: code_for_label("A"),
-                    lstickl: code_for_label("D"),
-                    buttona: code_for_label("Spacebar"),
-                    buttonb: code_for_label("Left Control"),
-                    buttonx: code_for_label("F"),
-                    buttony: code_for_label("1"),
-                    start: code_for_label("Escape"),
-                    shoulderl: code_for_label("Q"),
-                    shoulderr: code_for_label("E"),
-                    lthumb: code_for_label("Shift"),
-                    rthumb: code_for_label("V"),
-                    back: code_for_label("Tab"),
-                    left_autofire: false,
-                    right_autofire: false,
-                    movement_multiplier: 2000,
-                    sampling_interval: 2000,
-                    recoil_compensation_active: false,
-                    recoil_sideways_compensation: 0,
-                    recoil_vertical_compensation: 0,
-                    recoil_impulse_vertical: 0,
-                    recoil_impulse_duration: 0,
-                }
-            },
+            ..Default::default()
         }];
-        let cfg = Configuration { games };
-        std::fs::write(edir, serde_json::to_string_pretty(&cfg)?)?;
+        let cfg = Configuration {
+            version: CONFIG_VERSION,
+            games,
+            path: path.to_path_buf(),
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        cfg.save()?;
+
+        Ok(cfg)
+    }
 
+    /// Read the configuration at `path` as it stands, upgraded in memory but
+    /// neither validated nor rewritten, for tools which report on it.
+    pub fn inspect(path: &Path) -> Result<Self> {
+        let (mut cfg, _) = parse(&std::fs::read(path)?, Format::from_path(path))?;
+        cfg.path = path.to_path_buf();
         Ok(cfg)
     }
+
+    /// Read the configuration at `path`, upgrading it to the current version.
+    /// An upgraded file is rewritten in place, after copying the original to
+    /// a backup alongside it named for its version, e.g.
+    /// `configuration.v0.json.bak`. An existing backup is left alone.
+    ///
+    /// Problems `validate` finds do not stop the configuration loading, so
+    /// files saved by older versions keep working; callers report them.
+    fn read(path: &Path) -> Result<Self> {
+        let format = Format::from_path(path);
+        let (mut cfg, version) = parse(&std::fs::read(path)?, format)?;
+        cfg.path = path.to_path_buf();
+        if version < CONFIG_VERSION {
+            let backup = path.with_extension(format!("v{}.{}.bak", version, format.extension()));
+            if !backup.exists() {
+                std::fs::copy(path, &backup)?;
+            }
+            cfg.save()?;
+        }
+        Ok(cfg)
+    }
+}
+
+// Parse and upgrade a configuration, also returning the version it was
+// saved as.
+fn parse(bytes: &[u8], format: Format) -> Result<(Configuration, u32)> {
+    let mut value: Value = format.from_slice(bytes)?;
+    let version = migrate(&mut value)?;
+    let overrides = crate::inheritance::inherit(&mut value)?;
+    let mut cfg: Configuration = serde_json::from_value(value)?;
+    for (game, overrides) in cfg.games.iter_mut().zip(overrides) {
+        game.overrides = overrides;
+    }
+    Ok((cfg, version))
+}
+
+// `path` with `suffix` added to its file name.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+    sibling(path, &format!("{}.bak", n))
+}
+
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let temporary = sibling(path, "tmp");
+    let mut file = std::fs::File::create(&temporary)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(temporary, path)?;
+    Ok(())
+}
+
+// The explicitly requested configuration path, if any. An empty value does
+// not count.
+fn choose(flag: Option<PathBuf>, env: Option<OsString>) -> Option<PathBuf> {
+    flag.or_else(|| env.map(PathBuf::from))
+        .filter(|path| !path.as_os_str().is_empty())
+}
+
+fn user_config_path() -> Result<PathBuf> {
+    let dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    dir.map(|dir| config_in(&dir.join("serf"))).ok_or_else(|| {
+        anyhow!(
+            "Could not find a per-user configuration directory; use --config or {}",
+            CONFIG_ENV
+        )
+    })
+}
+
+// The configuration file in `dir`. Any format will do, though JSON is what
+// gets created. YAML files are also commonly named `.yml`.
+fn config_in(dir: &Path) -> PathBuf {
+    Format::ALL
+        .iter()
+        .map(Format::extension)
+        .chain(["yml"])
+        .map(|extension| dir.join(format!("configuration.{}", extension)))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| dir.join("configuration.json"))
+}
+
+/// Upgrade a configuration from whatever version it was saved as to
+/// `CONFIG_VERSION`, returning the version it started at. Files without a
+/// version predate versioning and count as version 0.
+pub fn migrate(value: &mut Value) -> Result<u32> {
+    let version = match value.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| anyhow!("Configuration version {} is not valid", version))?,
+    };
+    if version > CONFIG_VERSION {
+        bail!(
+            "Configuration version {} is newer than this build of serf understands ({})",
+            version,
+            CONFIG_VERSION
+        );
+    }
+    for (from, step) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        step(value)?;
+        value["version"] = (from as u32 + 1).into();
+    }
+    Ok(version)
+}
+
+// Version 0 is either a list of games, each with a flat `ButtonMapping` as
+// its controls, or a bare `ButtonMapping` from before there were games. The
+// latter becomes the only game. Missing mapping fields are filled in by
+// their serde defaults, and the legacy autofire flags by `ButtonMapping`.
+fn from_unversioned(value: &mut Value) -> Result<()> {
+    let Value::Object(fields) = value else {
+        bail!("Configuration is not a JSON object");
+    };
+    if !fields.contains_key("games") {
+        let controls = std::mem::take(fields);
+        fields.insert(
+            "games".into(),
+            serde_json::json!([{ "name": Game::default().name, "controls": controls }]),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unversioned() -> Value {
+        serde_json::json!({
+            "games": [{
+                "name": "Old game",
+                "controls": serde_json::to_value(crate::ButtonMapping::default()).unwrap(),
+            }]
+        })
+    }
+
+    #[test]
+    fn unversioned_configurations_are_stamped() {
+        let mut value = unversioned();
+        assert_eq!(migrate(&mut value).unwrap(), 0);
+        let cfg: Configuration = serde_json::from_value(value).unwrap();
+        assert_eq!(cfg.version, CONFIG_VERSION);
+        assert_eq!(cfg.games[0].name, "Old game");
+    }
+
+    #[test]
+    fn bare_button_mappings_become_a_game() {
+        let mut value = unversioned()["games"][0]["controls"].take();
+        value["walk"] = 0x5a.into();
+        migrate(&mut value).unwrap();
+        let cfg: Configuration = serde_json::from_value(value).unwrap();
+        assert_eq!(cfg.games.len(), 1);
+        assert_eq!(cfg.games[0].controls.walk, 0x5a);
+    }
+
+    #[test]
+    fn newer_configurations_are_refused() {
+        let mut value = unversioned();
+        value["version"] = (CONFIG_VERSION + 1).into();
+        assert!(migrate(&mut value).is_err());
+    }
+
+    #[test]
+    fn upgrades_back_up_the_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("configuration.json");
+        let original = serde_json::to_string(&unversioned()).unwrap();
+        std::fs::write(&path, &original).unwrap();
+
+        let cfg = Configuration::read(&path).unwrap();
+        assert_eq!(cfg.version, CONFIG_VERSION);
+        let backup = std::fs::read_to_string(dir.path().join("configuration.v0.json.bak")).unwrap();
+        assert_eq!(backup, original);
+        let rewritten: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(rewritten["version"], CONFIG_VERSION);
+    }
+
+    #[test]
+    fn configurations_with_errors_still_load() {
+        // Saved by the "New Game" button of old, with every field zeroed.
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("configuration.json");
+        let mut value = unversioned();
+        value["games"][0]["name"] = "New Game".into();
+        std::fs::write(&path, serde_json::to_string(&value).unwrap()).unwrap();
+
+        let cfg = Configuration::load(&path).unwrap();
+        assert_eq!(cfg.games[0].controls.sampling_interval, 0);
+        assert!(crate::validate(&cfg).iter().any(|d| {
+            d.severity == crate::Severity::Error && d.path == "games[0].controls.sampling_interval"
+        }));
+    }
+
+    #[test]
+    fn games_with_a_broken_parent_load_on_their_own_controls() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("configuration.json");
+        let mut value = unversioned();
+        value["games"][0]["parent"] = "Missing".into();
+        value["games"][0]["controls"] = serde_json::json!({ "walk_speed": 10 });
+        std::fs::write(&path, serde_json::to_string(&value).unwrap()).unwrap();
+
+        let cfg = Configuration::load(&path).unwrap();
+        assert_eq!(cfg.games[0].controls.walk_speed, 10);
+        assert_eq!(
+            cfg.games[0].controls.movement_multiplier,
+            crate::ButtonMapping::starter().movement_multiplier
+        );
+        assert!(crate::validate(&cfg)
+            .iter()
+            .any(|d| d.severity == crate::Severity::Error && d.path == "games[0].parent"));
+    }
+
+    #[test]
+    fn the_flag_beats_the_environment() {
+        let flag = Some(PathBuf::from("flag.json"));
+        let env = Some(OsString::from("env.json"));
+        assert_eq!(choose(flag, env.clone()), Some("flag.json".into()));
+        assert_eq!(choose(None, env), Some("env.json".into()));
+        assert_eq!(choose(None, Some(OsString::new())), None);
+    }
+
+    #[test]
+    fn saves_go_back_where_the_configuration_came_from() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("serf.json");
+
+        let mut cfg = Configuration::load(&path).unwrap();
+        assert_eq!(cfg.path(), path);
+        cfg.games[0].name = "Renamed".into();
+        cfg.save().unwrap();
+        assert_eq!(Configuration::load(&path).unwrap().games[0].name, "Renamed");
+        for entry in std::fs::read_dir(path.parent().unwrap()).unwrap() {
+            let name = entry.unwrap().file_name().into_string().unwrap();
+            assert!(name == "serf.json" || name.ends_with(".bak"), "{}", name);
+        }
+    }
+
+    #[test]
+    fn saves_keep_a_limited_number_of_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("configuration.json");
+        let mut cfg = Configuration::load(&path).unwrap();
+        for n in 0..BACKUPS + 3 {
+            cfg.games[0].name = format!("Save {}", n);
+            cfg.save().unwrap();
+        }
+
+        let last = format!("Save {}", BACKUPS + 1);
+        let (newest, _) =
+            parse(&std::fs::read(backup_path(&path, 1)).unwrap(), Format::Json).unwrap();
+        assert_eq!(newest.games[0].name, last);
+        assert!(backup_path(&path, BACKUPS).exists());
+        assert!(!backup_path(&path, BACKUPS + 1).exists());
+        assert!(!sibling(&path, "tmp").exists());
+    }
+
+    #[test]
+    fn every_format_reads_back_the_same_configuration() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = Configuration::load(&dir.path().join("configuration.json")).unwrap();
+        let controls = &mut cfg.games[0].controls;
+        controls.weapons = vec![crate::WeaponPreset {
+            recoil_pattern: vec![crate::RecoilPoint {
+                time: 50,
+                dx: -2,
+                dy: 7,
+            }],
+            ..Default::default()
+        }];
+        controls.active_weapon = Some(0);
+        controls.activation.buttona = crate::Activation {
+            mode: crate::ActivationMode::LongPress,
+            millis: 300,
+            alternate: Some(crate::GamepadOutput::Y),
+        };
+        controls.chords = vec![crate::Chord {
+            keys: vec![0xa0, 0x45],
+            targets: vec![crate::BindingTarget::Back],
+        }];
+        controls.script = "// tuned\nreport.press(\"A\");\n".into();
+        cfg.games.push(Game {
+            name: "Sequel".into(),
+            ..Default::default()
+        });
+        cfg.set_parent(1, Some(cfg.games[0].name.clone())).unwrap();
+        cfg.games[1].controls.walk_speed = 30;
+        cfg.note_overrides(1);
+        cfg.save().unwrap();
+
+        let json = Configuration::load(&dir.path().join("configuration.json")).unwrap();
+        assert_eq!(json.games, cfg.games);
+        for name in [
+            "configuration.toml",
+            "configuration.yaml",
+            "configuration.yml",
+        ] {
+            let path = dir.path().join(name);
+            Configuration::load(&dir.path().join("configuration.json"))
+                .unwrap()
+                .save_as(&path)
+                .unwrap();
+            let converted = Configuration::load(&path).unwrap();
+            assert_eq!(converted.games, json.games, "{}", name);
+            assert_eq!(converted.version, CONFIG_VERSION);
+        }
+        let toml = std::fs::read_to_string(dir.path().join("configuration.toml")).unwrap();
+        assert!(toml.contains("parent = \"CoD Mediocre Warfare\""));
+    }
+
+    #[test]
+    fn configurations_are_found_under_any_format_name() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(config_in(dir.path()), dir.path().join("configuration.json"));
+        let yml = dir.path().join("configuration.yml");
+        std::fs::write(&yml, "").unwrap();
+        assert_eq!(config_in(dir.path()), yml);
+        let toml = dir.path().join("configuration.toml");
+        std::fs::write(&toml, "").unwrap();
+        assert_eq!(config_in(dir.path()), toml);
+    }
+
+    #[test]
+    fn truncated_configurations_restore_from_the_newest_good_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("configuration.json");
+        let mut cfg = Configuration::load(&path).unwrap();
+        cfg.games[0].name = "Good".into();
+        for _ in 0..3 {
+            cfg.save().unwrap();
+        }
+        // The newest backup is damaged too, so the one before it is used.
+        let good = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &good[..good.len() / 2]).unwrap();
+        std::fs::write(backup_path(&path, 1), &good[..10]).unwrap();
+
+        assert!(Configuration::load(&path).is_err());
+        let backup = Configuration::newest_valid_backup(&path).unwrap();
+        assert_eq!(backup, backup_path(&path, 2));
+        let restored = Configuration::restore(&path, &backup).unwrap();
+        assert_eq!(restored.games[0].name, "Good");
+        assert!(sibling(&path, "broken").exists());
+        assert_eq!(Configuration::load(&path).unwrap().games[0].name, "Good");
+    }
 }
@@ -0,0 +1,68 @@
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use std::path::Path;
+
+/// File formats serf reads and writes, chosen by file extension.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Json, Format::Toml, Format::Yaml];
+
+    /// The format for `path`: TOML for `.toml` files, YAML for `.yaml` and
+    /// `.yml`, and JSON otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => Format::Toml,
+            Some(ext) if ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml") => {
+                Format::Yaml
+            }
+            _ => Format::Json,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Toml => "toml",
+            Format::Yaml => "yaml",
+        }
+    }
+
+    pub fn to_string<T: Serialize>(&self, value: &T) -> Result<String> {
+        Ok(match self {
+            Format::Json => serde_json::to_string_pretty(value)?,
+            Format::Toml => toml::to_string_pretty(&without_nulls(serde_json::to_value(value)?))?,
+            Format::Yaml => serde_norway::to_string(value)?,
+        })
+    }
+
+    pub fn from_slice<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        Ok(match self {
+            Format::Json => serde_json::from_slice(bytes)?,
+            Format::Toml => toml::from_str(std::str::from_utf8(bytes)?)?,
+            Format::Yaml => serde_norway::from_slice(bytes)?,
+        })
+    }
+}
+
+// TOML has no null, so unset optional values are left out instead, which
+// reads back the same.
+fn without_nulls(value: serde_json::Value) -> serde_json::Value {
+    use serde_json::Value;
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(name, value)| (name, without_nulls(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(without_nulls).collect()),
+        value => value,
+    }
+}
@@ -0,0 +1,402 @@
+use crate::{ButtonMapping, Configuration, Game};
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+
+// Games can inherit their controls from another game, their parent, naming
+// only the fields they change. On disk an inheriting game's `controls` hold
+// just those fields; in memory every game's `controls` are complete, with
+// `Game::overrides` recording which fields are its own. Fields overridden to
+// nothing, such as no active weapon, are listed in the game's `unset` rather
+// than stored as null, which TOML cannot hold.
+
+/// One field of an inheriting game's controls, and where its value is from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InheritedField {
+    pub name: String,
+    /// The value in brief, as it would be saved.
+    pub value: String,
+    pub overridden: bool,
+}
+
+// The top level fields of `controls`, by name.
+pub(crate) fn control_fields(controls: &ButtonMapping) -> Map<String, Value> {
+    match serde_json::to_value(controls) {
+        Ok(Value::Object(fields)) => fields,
+        _ => unreachable!("button mappings serialise as objects"),
+    }
+}
+
+// What is wrong with the chain of parents above game `ix`, if anything. A
+// parent is the first game with its name.
+pub(crate) fn ancestry_problem(
+    names: &[&str],
+    parents: &[Option<&str>],
+    ix: usize,
+) -> Option<String> {
+    let mut seen = vec![ix];
+    let mut current = ix;
+    while let Some(parent) = parents[current] {
+        let Some(next) = names.iter().position(|name| *name == parent) else {
+            return Some(format!(
+                "there is no game called '{}' to inherit from",
+                parent
+            ));
+        };
+        if seen.contains(&next) {
+            return Some(format!("'{}' ends up inheriting from itself", names[ix]));
+        }
+        seen.push(next);
+        current = next;
+    }
+    None
+}
+
+// The games' indices, parents before their children, each with the index of
+// the game it inherits from. Games whose chain of parents is broken inherit
+// nothing, keeping only their own controls, and are left for `validate` to
+// report.
+fn order(names: &[&str], parents: &[Option<&str>]) -> Vec<(usize, Option<usize>)> {
+    let parent_of: Vec<Option<usize>> = (0..names.len())
+        .map(|ix| {
+            let parent = parents[ix]?;
+            if ancestry_problem(names, parents, ix).is_some() {
+                return None;
+            }
+            names.iter().position(|name| *name == parent)
+        })
+        .collect();
+    let mut order: Vec<(usize, Option<usize>)> = vec![];
+    while order.len() < names.len() {
+        for (ix, parent) in parent_of.iter().enumerate() {
+            let done = |game: usize| order.iter().any(|(done, _)| *done == game);
+            let ready = match parent {
+                None => true,
+                Some(parent) => done(*parent),
+            };
+            if ready && !done(ix) {
+                order.push((ix, *parent));
+            }
+        }
+    }
+    order
+}
+
+fn parent_index(games: &[Game], ix: usize) -> Option<usize> {
+    let parent = games[ix].parent.as_deref()?;
+    games.iter().position(|game| game.name == parent)
+}
+
+// Fill in the stored controls of inheriting games from their parents,
+// returning the fields each game set itself.
+pub(crate) fn inherit(value: &mut Value) -> Result<Vec<BTreeSet<String>>> {
+    let Some(games) = value.get_mut("games").and_then(Value::as_array_mut) else {
+        return Ok(vec![]);
+    };
+    let names: Vec<String> = games
+        .iter()
+        .map(|game| game["name"].as_str().unwrap_or_default().to_string())
+        .collect();
+    let parents: Vec<Option<String>> = games
+        .iter()
+        .map(|game| game.get("parent").and_then(Value::as_str).map(String::from))
+        .collect();
+    let name_refs: Vec<&str> = names.iter().map(String::as_str).collect();
+    let parent_refs: Vec<Option<&str>> = parents.iter().map(Option::as_deref).collect();
+
+    let mut overrides = vec![BTreeSet::new(); games.len()];
+    for (ix, parent) in order(&name_refs, &parent_refs) {
+        if parent_refs[ix].is_none() {
+            continue;
+        }
+        let Some(own) = games[ix]["controls"].as_object() else {
+            bail!("games[{}].controls: expected the fields it overrides", ix);
+        };
+        let mut own = own.clone();
+        if let Some(game) = games[ix].as_object_mut() {
+            let unset = game.remove("unset").unwrap_or_default();
+            for field in unset
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+            {
+                own.insert(field.to_string(), Value::Null);
+            }
+        }
+        overrides[ix] = own.keys().cloned().collect();
+        let mut controls = match parent {
+            Some(parent) => games[parent]["controls"].clone(),
+            None => Value::Object(control_fields(&ButtonMapping::starter())),
+        };
+        if let Some(controls) = controls.as_object_mut() {
+            controls.extend(own);
+        }
+        games[ix]["controls"] = controls;
+    }
+    Ok(overrides)
+}
+
+// The configuration as saved, with inheriting games keeping only the fields
+// they override.
+pub(crate) fn stored(configuration: &Configuration) -> Result<Value> {
+    let mut value = serde_json::to_value(configuration)?;
+    if let Some(games) = value["games"].as_array_mut() {
+        for (game, stored) in configuration.games.iter().zip(games) {
+            if game.parent.is_some() {
+                let Some(controls) = stored["controls"].as_object_mut() else {
+                    continue;
+                };
+                controls.retain(|field, _| game.overrides.contains(field));
+                let unset: Vec<Value> = controls
+                    .iter()
+                    .filter(|(_, value)| value.is_null())
+                    .map(|(field, _)| field.clone().into())
+                    .collect();
+                controls.retain(|_, value| !value.is_null());
+                if !unset.is_empty() {
+                    stored["unset"] = unset.into();
+                }
+            }
+        }
+    }
+    Ok(value)
+}
+
+impl Configuration {
+    /// The game that game `ix` inherits from, if any.
+    pub fn parent_of(&self, ix: usize) -> Option<usize> {
+        parent_index(&self.games, ix)
+    }
+
+    /// Whether game `ix` may inherit from game `parent` without a loop.
+    pub fn can_inherit(&self, ix: usize, parent: usize) -> bool {
+        let mut current = Some(parent);
+        // Bounded, in case the games above `parent` already loop.
+        for _ in 0..=self.games.len() {
+            match current {
+                None => return true,
+                Some(game) if game == ix => return false,
+                Some(game) => current = parent_index(&self.games, game),
+            }
+        }
+        false
+    }
+
+    /// Copy every field an inheriting game does not override from its
+    /// parent, parents first, so edits to a game reach the games below it.
+    pub fn resolve(&mut self) -> Result<()> {
+        let names: Vec<&str> = self.games.iter().map(|g| g.name.as_str()).collect();
+        let parents: Vec<Option<&str>> = self.games.iter().map(|g| g.parent.as_deref()).collect();
+        for (ix, parent) in order(&names, &parents) {
+            let Some(parent) = parent else {
+                continue;
+            };
+            let inherited = control_fields(&self.games[parent].controls);
+            let game = &mut self.games[ix];
+            let mut fields = control_fields(&game.controls);
+            for (field, value) in inherited {
+                if !game.overrides.contains(&field) {
+                    fields.insert(field, value);
+                }
+            }
+            game.controls = serde_json::from_value(Value::Object(fields))?;
+        }
+        Ok(())
+    }
+
+    /// Each field of game `ix`'s controls, and whether it overrides the
+    /// parent's; empty if the game inherits nothing.
+    pub fn inheritance(&self, ix: usize) -> Vec<InheritedField> {
+        if self.games[ix].parent.is_none() {
+            return vec![];
+        }
+        control_fields(&self.games[ix].controls)
+            .into_iter()
+            .map(|(name, value)| {
+                let mut value = value.to_string();
+                if value.chars().count() > 40 {
+                    value = value.chars().take(39).chain(['\u{2026}']).collect();
+                }
+                InheritedField {
+                    overridden: self.games[ix].overrides.contains(&name),
+                    name,
+                    value,
+                }
+            })
+            .collect()
+    }
+
+    /// Count the fields of game `ix` which no longer match its parent as
+    /// overridden, as after editing them.
+    pub fn note_overrides(&mut self, ix: usize) {
+        let Some(parent) = parent_index(&self.games, ix) else {
+            return;
+        };
+        let inherited = control_fields(&self.games[parent].controls);
+        let game = &mut self.games[ix];
+        for (field, value) in control_fields(&game.controls) {
+            if inherited.get(&field) != Some(&value) {
+                game.overrides.insert(field);
+            }
+        }
+    }
+
+    /// Make game `ix` inherit from the game called `parent`, or from nothing.
+    /// Its controls stay as they are, overriding wherever they differ.
+    pub fn set_parent(&mut self, ix: usize, parent: Option<String>) -> Result<()> {
+        if let Some(name) = &parent {
+            match self.game_named(name) {
+                Some(parent) if self.can_inherit(ix, parent) => {}
+                Some(_) => bail!("'{}' already inherits from '{}'", name, self.games[ix].name),
+                None => bail!("There is no game called '{}'", name),
+            }
+        }
+        let game = &mut self.games[ix];
+        game.parent = parent;
+        game.overrides.clear();
+        self.note_overrides(ix);
+        Ok(())
+    }
+
+    /// Make `field` of game `ix` follow its parent again.
+    pub fn inherit_field(&mut self, ix: usize, field: &str) -> Result<()> {
+        self.games[ix].overrides.remove(field);
+        self.resolve()
+    }
+
+    /// Rename game `ix`, keeping the games which inherit from it. Games find
+    /// their parent by name, so a name another game has is refused.
+    pub fn rename_game(&mut self, ix: usize, name: String) -> Result<()> {
+        if (0..self.games.len()).any(|other| other != ix && self.games[other].name == name) {
+            bail!("There is already a game called '{}'", name);
+        }
+        // Should an earlier game share the old name, its children are not
+        // this game's.
+        let is_parent = self.game_named(&self.games[ix].name) == Some(ix);
+        let old = std::mem::replace(&mut self.games[ix].name, name.clone());
+        if is_parent {
+            for game in &mut self.games {
+                if game.parent.as_ref() == Some(&old) {
+                    game.parent = Some(name.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove game `ix`. Games which inherited from it keep its values as
+    /// their own.
+    pub fn remove_game(&mut self, ix: usize) {
+        let is_parent = self.game_named(&self.games[ix].name) == Some(ix);
+        let game = self.games.remove(ix);
+        if !is_parent {
+            return;
+        }
+        for child in &mut self.games {
+            if child.parent.as_ref() == Some(&game.name) {
+                child.parent = None;
+                child.overrides.clear();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(name: &str, parent: Option<&str>) -> Game {
+        Game {
+            name: name.into(),
+            parent: parent.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn children_store_only_what_they_override() {
+        let mut cfg = Configuration::default();
+        cfg.games = vec![game("Shooter", None), game("Sequel", Some("Shooter"))];
+        cfg.games[1].controls.movement_multiplier = 1500;
+        cfg.note_overrides(1);
+
+        let mut value = stored(&cfg).unwrap();
+        assert_eq!(
+            value["games"][1]["controls"],
+            serde_json::json!({ "movement_multiplier": 1500 })
+        );
+
+        // Editing the parent reaches the child, except where it overrides.
+        value["games"][0]["controls"]["movement_multiplier"] = 3000.into();
+        value["games"][0]["controls"]["walk_speed"] = 20.into();
+        let overrides = inherit(&mut value).unwrap();
+        assert_eq!(overrides[1], BTreeSet::from(["movement_multiplier".into()]));
+        let child: ButtonMapping =
+            serde_json::from_value(value["games"][1]["controls"].take()).unwrap();
+        assert_eq!(child.movement_multiplier, 1500);
+        assert_eq!(child.walk_speed, 20);
+    }
+
+    #[test]
+    fn edits_flow_down_through_grandchildren() {
+        let mut cfg = Configuration::default();
+        // Children may come before their parents.
+        cfg.games = vec![
+            game("Grandchild", Some("Child")),
+            game("Child", Some("Base")),
+            game("Base", None),
+        ];
+        cfg.games[1].controls.walk_speed = 10;
+        cfg.note_overrides(1);
+        cfg.games[2].controls.walk_speed = 70;
+        cfg.games[2].controls.movement_ramp = 40;
+        cfg.resolve().unwrap();
+        assert_eq!(cfg.games[0].controls.walk_speed, 10);
+        assert_eq!(cfg.games[0].controls.movement_ramp, 40);
+
+        cfg.inherit_field(1, "walk_speed").unwrap();
+        assert_eq!(cfg.games[0].controls.walk_speed, 70);
+    }
+
+    #[test]
+    fn loops_and_missing_parents_are_refused_or_ignored() {
+        let mut cfg = Configuration::default();
+        cfg.games = vec![game("A", Some("B")), game("B", None)];
+        assert!(!cfg.can_inherit(1, 0));
+        assert!(cfg.set_parent(1, Some("A".into())).is_err());
+
+        // Games already set up that way keep their own controls.
+        cfg.games[0].controls.walk_speed = 10;
+        cfg.games[0].overrides.clear();
+        cfg.games[1].parent = Some("A".into());
+        cfg.resolve().unwrap();
+        assert_eq!(cfg.games[0].controls.walk_speed, 10);
+        cfg.games[1].parent = Some("C".into());
+        cfg.resolve().unwrap();
+        assert_eq!(cfg.games[0].controls.walk_speed, 10);
+    }
+
+    #[test]
+    fn only_the_parent_of_a_shared_name_takes_its_children_along() {
+        let mut cfg = Configuration::default();
+        cfg.games = vec![
+            game("Shooter", None),
+            game("Shooter", None),
+            game("Sequel", Some("Shooter")),
+        ];
+        assert!(cfg.rename_game(2, "Shooter".into()).is_err());
+        assert_eq!(cfg.games[2].name, "Sequel");
+
+        cfg.rename_game(1, "Spinoff".into()).unwrap();
+        assert_eq!(cfg.parent_of(2), Some(0));
+        cfg.rename_game(0, "Original".into()).unwrap();
+        assert_eq!(cfg.games[2].parent.as_deref(), Some("Original"));
+
+        cfg.games[1].name = "Original".into();
+        cfg.remove_game(1);
+        assert_eq!(cfg.parent_of(1), Some(0));
+        cfg.remove_game(0);
+        assert_eq!(cfg.games[0].parent, None);
+    }
+}
@@ -4,6 +4,22 @@ pub use vkey::*;
 pub mod configuration;
 pub use configuration::*;
 
+pub mod watcher;
+pub use watcher::*;
+
+pub mod format;
+pub use format::*;
+
+pub mod profile;
+pub use profile::*;
+
+pub mod inheritance;
+pub use inheritance::*;
+
+pub mod validate;
+pub use validate::*;
+
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
 #[repr(usize)]
@@ -11,6 +27,656 @@ pub enum CopyTypes {
     ButtonMap = 0,
     CaptureMouse = 1,
     ReleaseMouse = 2,
+    QueryMetrics = 3,
+    Metrics = 4,
+    ScriptError = 5,
+}
+
+/// Upper bounds, in microseconds, of the input to output latency histogram
+/// buckets. The final bucket counts everything slower than the last bound.
+pub const LATENCY_BUCKETS: [u64; 7] = [250, 500, 1000, 2000, 4000, 8000, 16000];
+
+/// Keys the controller handles itself.
+pub const KEY_CAPTURE: i32 = 0x14; // Caps Lock
+pub const KEY_SENSITIVITY_DOWN: i32 = 0x70; // F1
+pub const KEY_SENSITIVITY_UP: i32 = 0x71; // F2
+pub const KEY_LEFT_FIRE_MODE: i32 = 0x74; // F5
+pub const KEY_RIGHT_FIRE_MODE: i32 = 0x75; // F6
+pub const KEY_RECOIL: i32 = 0x77; // F8
+
+/// The controller's own keys, with what each one does.
+pub const HOTKEYS: [(i32, &str); 6] = [
+    (KEY_CAPTURE, "toggles mouse capture"),
+    (KEY_SENSITIVITY_DOWN, "lowers sensitivity"),
+    (KEY_SENSITIVITY_UP, "raises sensitivity"),
+    (KEY_LEFT_FIRE_MODE, "cycles the left fire mode"),
+    (KEY_RIGHT_FIRE_MODE, "cycles the right fire mode"),
+    (KEY_RECOIL, "toggles recoil compensation"),
+];
+
+/// Controller loop telemetry, sent to the front end on request.
+#[repr(C)]
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Metrics {
+    /// Counts of reports by the delay between the input event which caused
+    /// them and the gamepad update.
+    pub latency_histogram: [u64; LATENCY_BUCKETS.len() + 1],
+    pub latency_max_micros: u64,
+    /// Reports sent to the virtual gamepad.
+    pub reports: u64,
+    /// Reports per second over the most recent measurement window.
+    pub report_rate: f64,
+    /// Periodic ticks which started late by more than a whole interval.
+    pub overruns: u64,
+    /// Reports carrying mouse movement, and how many of those were clipped by
+    /// the stick gate.
+    pub motion_reports: u64,
+    pub saturated_reports: u64,
+    /// Mouse counts lost to clipping at the edge of the stick gate.
+    pub dropped_motion: u64,
+}
+
+impl Metrics {
+    pub fn saturated_ratio(&self) -> f64 {
+        if self.motion_reports == 0 {
+            0.
+        } else {
+            self.saturated_reports as f64 / self.motion_reports as f64
+        }
+    }
+}
+
+/// Shape of the region the analog stick output is confined to.
+#[repr(u8)]
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum StickGate {
+    /// Each axis is clamped independently; diagonals reach the corners.
+    #[default]
+    Square = 0,
+    /// The output vector is limited to the unit circle.
+    Circle = 1,
+    /// The output vector is limited to an octagon with its vertices on the
+    /// cardinal and diagonal directions, as on many physical gamepads.
+    Octagon = 2,
+}
+
+impl From<u8> for StickGate {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => StickGate::Circle,
+            2 => StickGate::Octagon,
+            _ => StickGate::Square,
+        }
+    }
+}
+
+impl StickGate {
+    pub const ALL: [StickGate; 3] = [StickGate::Square, StickGate::Circle, StickGate::Octagon];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            StickGate::Square => "Square",
+            StickGate::Circle => "Circle",
+            StickGate::Octagon => "Octagon",
+        }
+    }
+}
+
+/// How to resolve simultaneous opposing directions (SOCD) on one stick axis,
+/// e.g. A and D held together.
+#[repr(u8)]
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum SocdMode {
+    /// The most recently pressed direction wins.
+    #[default]
+    LastInputWins = 0,
+    /// The direction that was held first wins.
+    FirstInputWins = 1,
+    /// Opposing directions cancel out.
+    Neutral = 2,
+    /// Up (vertical) or right (horizontal) always wins.
+    PositiveWins = 3,
+    /// Down (vertical) or left (horizontal) always wins.
+    NegativeWins = 4,
+}
+
+impl From<u8> for SocdMode {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => SocdMode::FirstInputWins,
+            2 => SocdMode::Neutral,
+            3 => SocdMode::PositiveWins,
+            4 => SocdMode::NegativeWins,
+            _ => SocdMode::LastInputWins,
+        }
+    }
+}
+
+impl SocdMode {
+    pub const ALL: [SocdMode; 5] = [
+        SocdMode::LastInputWins,
+        SocdMode::FirstInputWins,
+        SocdMode::Neutral,
+        SocdMode::PositiveWins,
+        SocdMode::NegativeWins,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SocdMode::LastInputWins => "Last input wins",
+            SocdMode::FirstInputWins => "First input wins",
+            SocdMode::Neutral => "Neutral",
+            SocdMode::PositiveWins => "Up / right wins",
+            SocdMode::NegativeWins => "Down / left wins",
+        }
+    }
+}
+
+/// What a trigger does while its mouse button is held.
+#[repr(u8)]
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum FireMode {
+    /// The trigger is held for as long as the button is.
+    #[default]
+    Hold = 0,
+    /// The trigger is pulsed at the autofire rate until the button is released.
+    Auto = 1,
+    /// The trigger is pulsed a fixed number of times at the autofire rate and
+    /// then released, even if the button is still held.
+    Burst = 2,
+}
+
+impl From<u8> for FireMode {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => FireMode::Auto,
+            2 => FireMode::Burst,
+            _ => FireMode::Hold,
+        }
+    }
+}
+
+impl FireMode {
+    pub const ALL: [FireMode; 3] = [FireMode::Hold, FireMode::Auto, FireMode::Burst];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FireMode::Hold => "Hold",
+            FireMode::Auto => "Auto fire",
+            FireMode::Burst => "Burst",
+        }
+    }
+
+    /// The mode after this one, for hotkeys which cycle through them.
+    pub fn next(&self) -> Self {
+        FireMode::from((*self as u8 + 1) % FireMode::ALL.len() as u8)
+    }
+}
+
+/// When recoil compensation is applied while the left mouse button is held.
+#[repr(u8)]
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum RecoilActivation {
+    /// Only while also aiming down sights with the right mouse button.
+    #[default]
+    WhileAiming = 0,
+    /// Whenever firing, including from the hip.
+    Always = 1,
+    /// Only while the recoil modifier key is held.
+    WithModifier = 2,
+}
+
+impl From<u8> for RecoilActivation {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => RecoilActivation::Always,
+            2 => RecoilActivation::WithModifier,
+            _ => RecoilActivation::WhileAiming,
+        }
+    }
+}
+
+impl RecoilActivation {
+    pub const ALL: [RecoilActivation; 3] = [
+        RecoilActivation::WhileAiming,
+        RecoilActivation::Always,
+        RecoilActivation::WithModifier,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RecoilActivation::WhileAiming => "While aiming",
+            RecoilActivation::Always => "Always",
+            RecoilActivation::WithModifier => "With modifier",
+        }
+    }
+}
+
+// Older configurations stored a `left_autofire`/`right_autofire` flag where
+// the fire mode now lives; accept either.
+fn fire_mode_or_autofire<'de, D>(deserializer: D) -> Result<FireMode, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Autofire(bool),
+        Mode(FireMode),
+    }
+    Ok(match Stored::deserialize(deserializer)? {
+        Stored::Autofire(true) => FireMode::Auto,
+        Stored::Autofire(false) => FireMode::Hold,
+        Stored::Mode(mode) => mode,
+    })
+}
+
+/// One point of a recoil compensation pattern. Offsets are percentages of
+/// full stick deflection, added to the right stick while firing.
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RecoilPoint {
+    /// Milliseconds since the trigger was pulled. Ignored for per-shot
+    /// patterns, where the nth point applies to the nth shot.
+    #[serde(default)]
+    pub time: i32,
+    pub dx: i32,
+    pub dy: i32,
+}
+
+/// A named set of firing and recoil settings for one weapon, which can be
+/// switched to mid-game. While a preset is active its values replace the
+/// left mouse button's fire mode, rate and recoil pattern.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WeaponPreset {
+    pub name: String,
+    /// Key which makes this preset active. It is still passed on to the game,
+    /// so it can be the same key the game uses to switch weapons.
+    #[serde(default)]
+    pub hotkey: i32,
+    #[serde(default)]
+    pub fire_mode: FireMode,
+    #[serde(default = "default_autofire_rpm")]
+    pub autofire_rpm: i32,
+    #[serde(default = "default_autofire_duty")]
+    pub autofire_duty: i32,
+    #[serde(default = "default_burst_count")]
+    pub burst_count: i32,
+    #[serde(default)]
+    pub recoil_pattern: Vec<RecoilPoint>,
+    #[serde(default)]
+    pub recoil_pattern_per_shot: bool,
+    /// Percentage of the movement rate to use while aiming down sights. There
+    /// is no setting outside presets, so with none active aiming is not slowed.
+    #[serde(default = "default_ads_sensitivity")]
+    pub ads_sensitivity: i32,
+}
+
+impl Default for WeaponPreset {
+    fn default() -> Self {
+        WeaponPreset {
+            name: "New weapon".into(),
+            hotkey: 0,
+            fire_mode: FireMode::default(),
+            autofire_rpm: default_autofire_rpm(),
+            autofire_duty: default_autofire_duty(),
+            burst_count: default_burst_count(),
+            recoil_pattern: vec![],
+            recoil_pattern_per_shot: false,
+            ads_sensitivity: default_ads_sensitivity(),
+        }
+    }
+}
+
+/// A single gamepad button or trigger, as driven by a macro.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum GamepadOutput {
+    A,
+    B,
+    X,
+    Y,
+    Start,
+    Back,
+    LeftShoulder,
+    RightShoulder,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    LeftTrigger,
+    RightTrigger,
+}
+
+impl GamepadOutput {
+    pub const ALL: [GamepadOutput; 16] = [
+        GamepadOutput::A,
+        GamepadOutput::B,
+        GamepadOutput::X,
+        GamepadOutput::Y,
+        GamepadOutput::Start,
+        GamepadOutput::Back,
+        GamepadOutput::LeftShoulder,
+        GamepadOutput::RightShoulder,
+        GamepadOutput::LeftThumb,
+        GamepadOutput::RightThumb,
+        GamepadOutput::DPadUp,
+        GamepadOutput::DPadDown,
+        GamepadOutput::DPadLeft,
+        GamepadOutput::DPadRight,
+        GamepadOutput::LeftTrigger,
+        GamepadOutput::RightTrigger,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GamepadOutput::A => "A",
+            GamepadOutput::B => "B",
+            GamepadOutput::X => "X",
+            GamepadOutput::Y => "Y",
+            GamepadOutput::Start => "Start",
+            GamepadOutput::Back => "Back",
+            GamepadOutput::LeftShoulder => "Left shoulder",
+            GamepadOutput::RightShoulder => "Right shoulder",
+            GamepadOutput::LeftThumb => "Left thumb",
+            GamepadOutput::RightThumb => "Right thumb",
+            GamepadOutput::DPadUp => "DPad Up",
+            GamepadOutput::DPadDown => "DPad Down",
+            GamepadOutput::DPadLeft => "DPad Left",
+            GamepadOutput::DPadRight => "DPad Right",
+            GamepadOutput::LeftTrigger => "Left trigger",
+            GamepadOutput::RightTrigger => "Right trigger",
+        }
+    }
+}
+
+/// One step of a macro.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum MacroStep {
+    Press {
+        output: GamepadOutput,
+    },
+    Release {
+        output: GamepadOutput,
+    },
+    /// Press, wait and release again.
+    Hold {
+        output: GamepadOutput,
+        millis: u32,
+    },
+    Wait {
+        millis: u32,
+    },
+}
+
+/// A timed sequence of gamepad actions, started by pressing `trigger`.
+/// Pressing it again while the macro runs cancels it.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Macro {
+    pub name: String,
+    #[serde(default)]
+    pub trigger: i32,
+    #[serde(default)]
+    pub steps: Vec<MacroStep>,
+}
+
+/// How a key press turns into a gamepad button press.
+#[repr(u8)]
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ActivationMode {
+    /// The button is held for as long as the key is.
+    #[default]
+    Hold = 0,
+    /// Each press of the key latches or unlatches the button.
+    Toggle = 1,
+    /// Each press of the key gives a fixed length press of the button.
+    Tap = 2,
+    /// A short press taps the button; holding the key past the threshold
+    /// holds the alternate output instead.
+    LongPress = 3,
+    /// A second press within the window holds the alternate output instead.
+    DoubleTap = 4,
+}
+
+impl ActivationMode {
+    pub const ALL: [ActivationMode; 5] = [
+        ActivationMode::Hold,
+        ActivationMode::Toggle,
+        ActivationMode::Tap,
+        ActivationMode::LongPress,
+        ActivationMode::DoubleTap,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ActivationMode::Hold => "Hold",
+            ActivationMode::Toggle => "Toggle",
+            ActivationMode::Tap => "Tap",
+            ActivationMode::LongPress => "Long press",
+            ActivationMode::DoubleTap => "Double tap",
+        }
+    }
+}
+
+/// Activation settings for one binding.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Activation {
+    pub mode: ActivationMode,
+    /// Tap length, long press threshold or double tap window, as the mode needs.
+    pub millis: i32,
+    /// What a long press or double tap produces.
+    pub alternate: Option<GamepadOutput>,
+}
+
+impl Default for Activation {
+    fn default() -> Self {
+        Activation {
+            mode: ActivationMode::Hold,
+            millis: 200,
+            alternate: None,
+        }
+    }
+}
+
+/// Activation settings for each gamepad button's binding.
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Activations {
+    pub start: Activation,
+    pub dpadu: Activation,
+    pub dpadd: Activation,
+    pub dpadl: Activation,
+    pub dpadr: Activation,
+    pub buttona: Activation,
+    pub buttonb: Activation,
+    pub buttonx: Activation,
+    pub buttony: Activation,
+    pub shoulderl: Activation,
+    pub shoulderr: Activation,
+    pub lthumb: Activation,
+    pub rthumb: Activation,
+    pub back: Activation,
+}
+
+/// Auto-repeat rate, in presses per minute, for each gamepad button while its
+/// key is held. Zero leaves the button as a plain hold.
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TurboRates {
+    pub start: i32,
+    pub dpadu: i32,
+    pub dpadd: i32,
+    pub dpadl: i32,
+    pub dpadr: i32,
+    pub buttona: i32,
+    pub buttonb: i32,
+    pub buttonx: i32,
+    pub buttony: i32,
+    pub shoulderl: i32,
+    pub shoulderr: i32,
+    pub lthumb: i32,
+    pub rthumb: i32,
+    pub back: i32,
+}
+
+/// A key code for each of the controller's key bindings. Zero leaves the
+/// binding unset.
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub start: i32,
+    pub dpadu: i32,
+    pub dpadd: i32,
+    pub dpadr: i32,
+    pub dpadl: i32,
+    pub buttona: i32,
+    pub buttonb: i32,
+    pub buttonx: i32,
+    pub buttony: i32,
+    pub shoulderl: i32,
+    pub shoulderr: i32,
+    pub lthumb: i32,
+    pub rthumb: i32,
+    pub back: i32,
+    pub lstickd: i32,
+    pub lsticku: i32,
+    pub lstickr: i32,
+    pub lstickl: i32,
+    pub walk: i32,
+}
+
+impl KeyBindings {
+    /// The codes in the order the controller numbers its bindings.
+    pub fn codes(&self) -> [i32; 19] {
+        [
+            self.start,
+            self.dpadu,
+            self.dpadd,
+            self.dpadr,
+            self.dpadl,
+            self.buttona,
+            self.buttonb,
+            self.buttonx,
+            self.buttony,
+            self.shoulderl,
+            self.shoulderr,
+            self.lthumb,
+            self.rthumb,
+            self.back,
+            self.lstickd,
+            self.lsticku,
+            self.lstickr,
+            self.lstickl,
+            self.walk,
+        ]
+    }
+}
+
+/// One of the controller's key bindings, in the order of `KeyBindings::codes`.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum BindingTarget {
+    Start,
+    DPadUp,
+    DPadDown,
+    DPadRight,
+    DPadLeft,
+    A,
+    B,
+    X,
+    Y,
+    LeftShoulder,
+    RightShoulder,
+    LeftThumb,
+    RightThumb,
+    Back,
+    StickDown,
+    StickUp,
+    StickRight,
+    StickLeft,
+    Walk,
+}
+
+impl BindingTarget {
+    pub const ALL: [BindingTarget; 19] = [
+        BindingTarget::Start,
+        BindingTarget::DPadUp,
+        BindingTarget::DPadDown,
+        BindingTarget::DPadRight,
+        BindingTarget::DPadLeft,
+        BindingTarget::A,
+        BindingTarget::B,
+        BindingTarget::X,
+        BindingTarget::Y,
+        BindingTarget::LeftShoulder,
+        BindingTarget::RightShoulder,
+        BindingTarget::LeftThumb,
+        BindingTarget::RightThumb,
+        BindingTarget::Back,
+        BindingTarget::StickDown,
+        BindingTarget::StickUp,
+        BindingTarget::StickRight,
+        BindingTarget::StickLeft,
+        BindingTarget::Walk,
+    ];
+
+    /// Position in `KeyBindings::codes`.
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BindingTarget::Start => "Start",
+            BindingTarget::DPadUp => "DPad Up",
+            BindingTarget::DPadDown => "DPad Down",
+            BindingTarget::DPadRight => "DPad Right",
+            BindingTarget::DPadLeft => "DPad Left",
+            BindingTarget::A => "A",
+            BindingTarget::B => "B",
+            BindingTarget::X => "X",
+            BindingTarget::Y => "Y",
+            BindingTarget::LeftShoulder => "Left shoulder",
+            BindingTarget::RightShoulder => "Right shoulder",
+            BindingTarget::LeftThumb => "Left thumb",
+            BindingTarget::RightThumb => "Right thumb",
+            BindingTarget::Back => "Back",
+            BindingTarget::StickDown => "Stick Down",
+            BindingTarget::StickUp => "Stick Up",
+            BindingTarget::StickRight => "Stick Right",
+            BindingTarget::StickLeft => "Stick Left",
+            BindingTarget::Walk => "Walk",
+        }
+    }
+}
+
+/// A binding beyond the single key per button: the last key in `keys`
+/// triggers it, provided the keys before it (if any) are already held, and it
+/// drives every one of `targets`. A chord with one key is simply an extra key
+/// for its targets.
+#[derive(Default, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Chord {
+    pub keys: Vec<i32>,
+    pub targets: Vec<BindingTarget>,
+}
+
+/// An alternate set of key bindings, active while its key is held or, for a
+/// toggled layer, between presses of its key. Keys the layer leaves unset
+/// fall through to the layers beneath it and finally to the base bindings.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Layer {
+    pub name: String,
+    #[serde(default)]
+    pub key: i32,
+    #[serde(default)]
+    pub toggle: bool,
+    #[serde(default)]
+    pub bindings: KeyBindings,
+    #[serde(default)]
+    pub chords: Vec<Chord>,
 }
 
 #[derive(Default, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -33,10 +699,18 @@ pub struct ButtonMapping {
     pub rthumb: i32,
     pub start: i32,
     pub back: i32,
-    #[serde(default)]
-    pub left_autofire: bool,
-    #[serde(default)]
-    pub right_autofire: bool,
+    #[serde(
+        default,
+        alias = "left_autofire",
+        deserialize_with = "fire_mode_or_autofire"
+    )]
+    pub left_fire_mode: FireMode,
+    #[serde(
+        default,
+        alias = "right_autofire",
+        deserialize_with = "fire_mode_or_autofire"
+    )]
+    pub right_fire_mode: FireMode,
     pub movement_multiplier: i16,
     pub sampling_interval: u64,
     #[serde(default)]
@@ -49,4 +723,145 @@ pub struct ButtonMapping {
     pub recoil_impulse_vertical: i32,
     #[serde(default)]
     pub recoil_impulse_duration: i32,
+    #[serde(default)]
+    pub recoil_activation: RecoilActivation,
+    /// Key which enables compensation in the `WithModifier` activation mode.
+    #[serde(default)]
+    pub recoil_modifier: i32,
+    #[serde(default)]
+    pub stick_gate: StickGate,
+    #[serde(default)]
+    pub socd_horizontal: SocdMode,
+    #[serde(default)]
+    pub socd_vertical: SocdMode,
+    #[serde(default)]
+    pub walk: i32,
+    #[serde(default = "default_walk_speed")]
+    pub walk_speed: i32,
+    #[serde(default)]
+    pub movement_ramp: i32,
+    #[serde(default = "default_autofire_rpm")]
+    pub left_autofire_rpm: i32,
+    #[serde(default = "default_autofire_duty")]
+    pub left_autofire_duty: i32,
+    #[serde(default = "default_autofire_rpm")]
+    pub right_autofire_rpm: i32,
+    #[serde(default = "default_autofire_duty")]
+    pub right_autofire_duty: i32,
+    #[serde(default = "default_burst_count")]
+    pub left_burst_count: i32,
+    #[serde(default = "default_burst_count")]
+    pub right_burst_count: i32,
+    #[serde(default)]
+    pub turbo: TurboRates,
+    #[serde(default)]
+    pub activation: Activations,
+    #[serde(default)]
+    pub recoil_pattern: Vec<RecoilPoint>,
+    #[serde(default)]
+    pub recoil_pattern_per_shot: bool,
+    #[serde(default)]
+    pub weapons: Vec<WeaponPreset>,
+    /// Index into `weapons` of the preset in use, if any.
+    #[serde(default)]
+    pub active_weapon: Option<usize>,
+    /// Let the mouse wheel cycle through the weapon presets.
+    #[serde(default)]
+    pub weapon_wheel: bool,
+    #[serde(default)]
+    pub macros: Vec<Macro>,
+    #[serde(default)]
+    pub chords: Vec<Chord>,
+    /// Binding layers; when several are active the later ones take precedence.
+    #[serde(default)]
+    pub layers: Vec<Layer>,
+    /// Rhai source run by the controller for every report; empty for none.
+    #[serde(default)]
+    pub script: String,
+}
+
+impl ButtonMapping {
+    /// Serialise for sending between the front end and the controller.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+
+    /// The base key bindings.
+    pub fn bindings(&self) -> KeyBindings {
+        KeyBindings {
+            start: self.start,
+            dpadu: self.dpadu,
+            dpadd: self.dpadd,
+            dpadr: self.dpadr,
+            dpadl: self.dpadl,
+            buttona: self.buttona,
+            buttonb: self.buttonb,
+            buttonx: self.buttonx,
+            buttony: self.buttony,
+            shoulderl: self.shoulderl,
+            shoulderr: self.shoulderr,
+            lthumb: self.lthumb,
+            rthumb: self.rthumb,
+            back: self.back,
+            lstickd: self.lstickd,
+            lsticku: self.lsticku,
+            lstickr: self.lstickr,
+            lstickl: self.lstickl,
+            walk: self.walk,
+        }
+    }
+}
+
+fn default_walk_speed() -> i32 {
+    50
+}
+
+fn default_autofire_rpm() -> i32 {
+    800
+}
+
+fn default_autofire_duty() -> i32 {
+    50
+}
+
+fn default_burst_count() -> i32 {
+    3
+}
+
+fn default_ads_sensitivity() -> i32 {
+    100
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn autofire_flags_migrate_to_fire_modes() {
+        let mut value = serde_json::to_value(ButtonMapping::default()).unwrap();
+        let fields = value.as_object_mut().unwrap();
+        fields.remove("left_fire_mode");
+        fields.remove("right_fire_mode");
+        fields.insert("left_autofire".into(), true.into());
+        fields.insert("right_autofire".into(), false.into());
+
+        let map: ButtonMapping = serde_json::from_value(value).unwrap();
+        assert_eq!(map.left_fire_mode, FireMode::Auto);
+        assert_eq!(map.right_fire_mode, FireMode::Hold);
+    }
+
+    #[test]
+    fn fire_modes_round_trip() {
+        let map = ButtonMapping {
+            left_fire_mode: FireMode::Burst,
+            right_fire_mode: FireMode::Auto,
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&map).unwrap();
+        assert_eq!(serde_json::from_str::<ButtonMapping>(&json).unwrap(), map);
+    }
 }
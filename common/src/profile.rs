@@ -0,0 +1,261 @@
+use crate::{ButtonMapping, Configuration, Format, Game, Severity, CONFIG_VERSION};
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The serf release this build belongs to.
+pub const SERF_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// One game's controls as a file of its own, for sharing with others. The
+/// format, JSON, TOML or YAML, follows the file extension.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    /// The configuration version the controls were written by.
+    pub version: u32,
+    #[serde(default)]
+    pub author: String,
+    /// Name of the game the controls are for.
+    pub game: String,
+    /// The day the profile was exported, as `YYYY-MM-DD`.
+    #[serde(default)]
+    pub created: String,
+    /// The version of serf which exported the profile.
+    #[serde(default)]
+    pub serf_version: String,
+    pub controls: ButtonMapping,
+}
+
+/// What to do when importing a profile for a game the configuration already
+/// has.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum OnConflict {
+    Refuse,
+    /// Replace the existing game's controls.
+    Replace,
+    /// Keep both, numbering the imported game's name.
+    Rename,
+}
+
+impl Profile {
+    pub fn new(game: &Game, author: &str) -> Self {
+        Profile {
+            version: CONFIG_VERSION,
+            author: author.to_string(),
+            game: game.name.clone(),
+            created: today(),
+            serf_version: SERF_VERSION.to_string(),
+            controls: game.controls.clone(),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, Format::from_path(path).to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Read a profile, upgrading controls written by older versions of serf
+    /// and refusing ones from newer versions and ones whose controls have
+    /// errors.
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut value: Value = Format::from_path(path).from_slice(&std::fs::read(path)?)?;
+        let Value::Object(fields) = &mut value else {
+            bail!("The profile is not a JSON object");
+        };
+        let version = fields
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| anyhow!("The profile has no valid version"))?;
+        if version > u64::from(CONFIG_VERSION) {
+            bail!(
+                "The profile is version {}, newer than this serf understands ({})",
+                version,
+                CONFIG_VERSION
+            );
+        }
+        // The controls take the same upgrades as a configuration's, so migrate
+        // a configuration holding only them.
+        let mut configuration = serde_json::json!({
+            "version": version,
+            "games": [{ "name": "", "controls": fields.remove("controls") }],
+        });
+        crate::migrate(&mut configuration)?;
+        fields.insert(
+            "controls".into(),
+            configuration["games"][0]["controls"].take(),
+        );
+        fields.insert("version".into(), CONFIG_VERSION.into());
+        let profile: Profile = serde_json::from_value(value)?;
+        let errors: Vec<String> = crate::validate_mapping(&profile.controls, "controls")
+            .into_iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.to_string())
+            .collect();
+        if !errors.is_empty() {
+            bail!("Invalid profile:\n{}", errors.join("\n"));
+        }
+        Ok(profile)
+    }
+}
+
+impl Configuration {
+    /// The index of the game called `name`, if there is one.
+    pub fn game_named(&self, name: &str) -> Option<usize> {
+        self.games.iter().position(|game| game.name == name)
+    }
+
+    /// `name` if no game has it yet, otherwise the first of `name (2)`,
+    /// `name (3)` and so on which is free.
+    pub fn unused_name(&self, name: &str) -> String {
+        if self.game_named(name).is_none() {
+            return name.to_string();
+        }
+        (2..)
+            .map(|n| format!("{} ({})", name, n))
+            .find(|name| self.game_named(name).is_none())
+            .expect("some number is free")
+    }
+
+    /// Add the game from `profile`, returning its index.
+    pub fn import(&mut self, profile: Profile, on_conflict: OnConflict) -> Result<usize> {
+        let Some(existing) = self.game_named(&profile.game) else {
+            self.games.push(Game {
+                name: profile.game,
+                controls: profile.controls,
+                ..Default::default()
+            });
+            return Ok(self.games.len() - 1);
+        };
+        match on_conflict {
+            OnConflict::Refuse => bail!("There is already a game called '{}'", profile.game),
+            OnConflict::Replace => {
+                // The profile's controls are complete, so nothing is inherited.
+                self.set_parent(existing, None)?;
+                self.games[existing].controls = profile.controls;
+                Ok(existing)
+            }
+            OnConflict::Rename => {
+                self.games.push(Game {
+                    name: self.unused_name(&profile.game),
+                    controls: profile.controls,
+                    ..Default::default()
+                });
+                Ok(self.games.len() - 1)
+            }
+        }
+    }
+}
+
+// Today's UTC date as `YYYY-MM-DD`.
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86_400)
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// The proleptic Gregorian date `days` after 1970-01-01, after Howard
+// Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(name: &str) -> Profile {
+        Profile::new(
+            &Game {
+                name: name.into(),
+                ..Default::default()
+            },
+            "Tester",
+        )
+    }
+
+    #[test]
+    fn profiles_round_trip_through_json_and_toml() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut original = profile("Shooter");
+        original.controls.layers = vec![crate::Layer {
+            name: "Alt".into(),
+            key: 0xa4,
+            toggle: true,
+            bindings: crate::KeyBindings::default(),
+            chords: vec![],
+        }];
+        original.controls.macros = vec![crate::Macro {
+            name: "Slide".into(),
+            trigger: 0x43,
+            steps: vec![crate::MacroStep::Hold {
+                output: crate::GamepadOutput::B,
+                millis: 120,
+            }],
+        }];
+        for name in ["shooter.json", "shooter.toml"] {
+            let path = dir.path().join(name);
+            original.write(&path).unwrap();
+            assert_eq!(Profile::read(&path).unwrap(), original);
+        }
+        let toml = std::fs::read_to_string(dir.path().join("shooter.toml")).unwrap();
+        assert!(toml.contains("author = \"Tester\""));
+    }
+
+    #[test]
+    fn older_profiles_are_upgraded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("old.json");
+        let mut old = serde_json::to_value(profile("Shooter")).unwrap();
+        old["version"] = 0.into();
+        std::fs::write(&path, old.to_string()).unwrap();
+        let read = Profile::read(&path).unwrap();
+        assert_eq!(read.version, CONFIG_VERSION);
+        assert_eq!(read.controls, profile("Shooter").controls);
+
+        old["version"] = (CONFIG_VERSION + 1).into();
+        std::fs::write(&path, old.to_string()).unwrap();
+        assert!(Profile::read(&path).is_err());
+    }
+
+    #[test]
+    fn conflicting_imports_are_refused_replaced_or_renamed() {
+        let mut cfg = Configuration::default();
+        cfg.import(profile("Shooter"), OnConflict::Refuse).unwrap();
+        let mut tuned = profile("Shooter");
+        tuned.controls.movement_multiplier = 1234;
+
+        assert!(cfg.import(tuned.clone(), OnConflict::Refuse).is_err());
+        assert_eq!(cfg.import(tuned.clone(), OnConflict::Replace).unwrap(), 0);
+        assert_eq!(cfg.games[0].controls.movement_multiplier, 1234);
+        assert_eq!(cfg.import(tuned.clone(), OnConflict::Rename).unwrap(), 1);
+        assert_eq!(cfg.import(tuned, OnConflict::Rename).unwrap(), 2);
+        assert_eq!(cfg.games[2].name, "Shooter (3)");
+    }
+
+    #[test]
+    fn profiles_record_the_serf_release() {
+        let manifest: toml::Value = toml::from_str(include_str!("../../Cargo.toml")).unwrap();
+        let release = manifest["workspace"]["package"]["version"].as_str();
+        assert_eq!(release, Some(SERF_VERSION));
+        assert_eq!(profile("Shooter").serf_version, SERF_VERSION);
+    }
+
+    #[test]
+    fn dates_are_counted_from_the_epoch() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+    }
+}
@@ -0,0 +1,485 @@
+use crate::inheritance::ancestry_problem;
+use crate::vkey::label_for_code;
+use crate::{ButtonMapping, Configuration, FireMode, KeyBindings, RecoilPoint, HOTKEYS};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Works, but probably not as intended.
+    Warning,
+    /// Would misbehave; configurations with errors load, but are not saved.
+    Error,
+}
+
+/// One problem with a configuration, and where it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Path to the offending value, e.g. `games[0].controls.sampling_interval`.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}: {}", severity, self.path, self.message)
+    }
+}
+
+// Field names of `KeyBindings`, and of the matching `ButtonMapping` fields,
+// in `KeyBindings::codes` order.
+const BINDING_FIELDS: [&str; 19] = [
+    "start",
+    "dpadu",
+    "dpadd",
+    "dpadr",
+    "dpadl",
+    "buttona",
+    "buttonb",
+    "buttonx",
+    "buttony",
+    "shoulderl",
+    "shoulderr",
+    "lthumb",
+    "rthumb",
+    "back",
+    "lstickd",
+    "lsticku",
+    "lstickr",
+    "lstickl",
+    "walk",
+];
+
+struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    fn error(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.0.push(Diagnostic {
+            severity: Severity::Error,
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    fn warning(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.0.push(Diagnostic {
+            severity: Severity::Warning,
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    // Keys the controller keeps for itself are swallowed by a binding while
+    // the mouse is captured, so the hotkey stops working.
+    fn hotkey(&mut self, path: &str, code: i32) {
+        if let Some((_, action)) = HOTKEYS.iter().find(|(key, _)| *key == code) {
+            self.warning(
+                path,
+                format!("{} is also the key which {}", label_for_code(&code), action),
+            );
+        }
+    }
+
+    fn bindings(&mut self, path: &str, bindings: &KeyBindings) {
+        let codes = bindings.codes();
+        for (ix, &code) in codes.iter().enumerate() {
+            if code == 0 {
+                continue;
+            }
+            let field = format!("{}.{}", path, BINDING_FIELDS[ix]);
+            self.hotkey(&field, code);
+            if let Some(first) = codes[..ix].iter().position(|c| *c == code) {
+                self.warning(
+                    field,
+                    format!(
+                        "{} is already bound to {}; it will press both",
+                        label_for_code(&code),
+                        BINDING_FIELDS[first]
+                    ),
+                );
+            }
+        }
+    }
+
+    fn fire_mode(&mut self, path: &str, mode: FireMode, rpm: i32, duty: i32, burst: i32) {
+        if mode == FireMode::Hold {
+            return;
+        }
+        if !(1..=60_000).contains(&rpm) {
+            self.warning(
+                format!("{}_rpm", path),
+                format!(
+                    "{} rounds per minute is outside 1 to 60000 and will be clamped",
+                    rpm
+                ),
+            );
+        }
+        if !(0..=100).contains(&duty) {
+            self.warning(
+                format!("{}_duty", path),
+                format!("{}% is outside 0 to 100 and will be clamped", duty),
+            );
+        }
+        if mode == FireMode::Burst && burst < 1 {
+            self.error(
+                path.replace("autofire", "burst_count"),
+                "a burst needs at least one shot",
+            );
+        }
+    }
+
+    fn recoil_pattern(&mut self, path: &str, pattern: &[RecoilPoint], per_shot: bool) {
+        if per_shot {
+            return;
+        }
+        for (ix, point) in pattern.iter().enumerate() {
+            if point.time < 0 {
+                self.error(format!("{}[{}].time", path, ix), "must not be negative");
+            }
+        }
+    }
+
+    fn not_negative(&mut self, path: impl Into<String>, value: i32) {
+        if value < 0 {
+            self.error(path, format!("{} must not be negative", value));
+        }
+    }
+}
+
+/// Check a whole configuration.
+pub fn validate(configuration: &Configuration) -> Vec<Diagnostic> {
+    let mut diagnostics = Diagnostics(vec![]);
+    if configuration.games.is_empty() {
+        diagnostics.error("games", "there must be at least one game");
+    }
+    let names: Vec<&str> = configuration
+        .games
+        .iter()
+        .map(|g| g.name.as_str())
+        .collect();
+    let parents: Vec<Option<&str>> = configuration
+        .games
+        .iter()
+        .map(|g| g.parent.as_deref())
+        .collect();
+    for (ix, game) in configuration.games.iter().enumerate() {
+        let path = format!("games[{}]", ix);
+        if let Some(problem) = ancestry_problem(&names, &parents, ix) {
+            diagnostics.error(format!("{}.parent", path), problem);
+        }
+        if configuration.games[..ix]
+            .iter()
+            .any(|g| g.name == game.name)
+        {
+            diagnostics.error(
+                format!("{}.name", path),
+                format!(
+                    "there is already a game called '{}'; games inherit by name",
+                    game.name
+                ),
+            );
+        }
+        diagnostics.0.extend(validate_mapping(
+            &game.controls,
+            &format!("{}.controls", path),
+        ));
+    }
+    diagnostics.0
+}
+
+/// Check one game's controls, with paths starting from `path`.
+pub fn validate_mapping(map: &ButtonMapping, path: &str) -> Vec<Diagnostic> {
+    let mut d = Diagnostics(vec![]);
+    let field = |name: &str| format!("{}.{}", path, name);
+
+    if map.sampling_interval == 0 {
+        d.error(
+            field("sampling_interval"),
+            "must be above zero; zero makes the controller spin in a busy loop",
+        );
+    }
+    if map.movement_multiplier == 0 {
+        d.warning(
+            field("movement_multiplier"),
+            "zero stops the mouse moving the stick",
+        );
+    }
+    d.not_negative(
+        field("recoil_impulse_duration"),
+        map.recoil_impulse_duration,
+    );
+    d.not_negative(field("movement_ramp"), map.movement_ramp);
+    if !(0..=100).contains(&map.walk_speed) {
+        d.warning(
+            field("walk_speed"),
+            format!("{}% is outside 0 to 100", map.walk_speed),
+        );
+    }
+    d.fire_mode(
+        &field("left_autofire"),
+        map.left_fire_mode,
+        map.left_autofire_rpm,
+        map.left_autofire_duty,
+        map.left_burst_count,
+    );
+    d.fire_mode(
+        &field("right_autofire"),
+        map.right_fire_mode,
+        map.right_autofire_rpm,
+        map.right_autofire_duty,
+        map.right_burst_count,
+    );
+    d.recoil_pattern(
+        &field("recoil_pattern"),
+        &map.recoil_pattern,
+        map.recoil_pattern_per_shot,
+    );
+
+    let base = map.bindings();
+    d.bindings(path, &base);
+    d.hotkey(&field("recoil_modifier"), map.recoil_modifier);
+
+    let turbo = &map.turbo;
+    for (name, rate) in [
+        ("start", turbo.start),
+        ("dpadu", turbo.dpadu),
+        ("dpadd", turbo.dpadd),
+        ("dpadl", turbo.dpadl),
+        ("dpadr", turbo.dpadr),
+        ("buttona", turbo.buttona),
+        ("buttonb", turbo.buttonb),
+        ("buttonx", turbo.buttonx),
+        ("buttony", turbo.buttony),
+        ("shoulderl", turbo.shoulderl),
+        ("shoulderr", turbo.shoulderr),
+        ("lthumb", turbo.lthumb),
+        ("rthumb", turbo.rthumb),
+        ("back", turbo.back),
+    ] {
+        d.not_negative(field(&format!("turbo.{}", name)), rate);
+    }
+    let activation = &map.activation;
+    for (name, activation) in [
+        ("start", activation.start),
+        ("dpadu", activation.dpadu),
+        ("dpadd", activation.dpadd),
+        ("dpadl", activation.dpadl),
+        ("dpadr", activation.dpadr),
+        ("buttona", activation.buttona),
+        ("buttonb", activation.buttonb),
+        ("buttonx", activation.buttonx),
+        ("buttony", activation.buttony),
+        ("shoulderl", activation.shoulderl),
+        ("shoulderr", activation.shoulderr),
+        ("lthumb", activation.lthumb),
+        ("rthumb", activation.rthumb),
+        ("back", activation.back),
+    ] {
+        d.not_negative(
+            field(&format!("activation.{}.millis", name)),
+            activation.millis,
+        );
+    }
+
+    for (ix, weapon) in map.weapons.iter().enumerate() {
+        let weapon_path = field(&format!("weapons[{}]", ix));
+        d.fire_mode(
+            &format!("{}.autofire", weapon_path),
+            weapon.fire_mode,
+            weapon.autofire_rpm,
+            weapon.autofire_duty,
+            weapon.burst_count,
+        );
+        d.recoil_pattern(
+            &format!("{}.recoil_pattern", weapon_path),
+            &weapon.recoil_pattern,
+            weapon.recoil_pattern_per_shot,
+        );
+        d.not_negative(
+            format!("{}.ads_sensitivity", weapon_path),
+            weapon.ads_sensitivity,
+        );
+        if weapon.hotkey != 0
+            && map.weapons[..ix]
+                .iter()
+                .any(|other| other.hotkey == weapon.hotkey)
+        {
+            d.warning(
+                format!("{}.hotkey", weapon_path),
+                format!(
+                    "{} already selects another preset",
+                    label_for_code(&weapon.hotkey)
+                ),
+            );
+        }
+    }
+    if let Some(active) = map.active_weapon {
+        if active >= map.weapons.len() {
+            d.error(
+                field("active_weapon"),
+                format!("there is no weapon preset {}", active),
+            );
+        }
+    }
+
+    for (ix, m) in map.macros.iter().enumerate() {
+        let trigger = field(&format!("macros[{}].trigger", ix));
+        if m.trigger == 0 {
+            continue;
+        }
+        d.hotkey(&trigger, m.trigger);
+        if let Some(binding) = base.codes().iter().position(|c| *c == m.trigger) {
+            d.warning(
+                trigger,
+                format!(
+                    "{} is also bound to {}, which it will no longer press",
+                    label_for_code(&m.trigger),
+                    BINDING_FIELDS[binding]
+                ),
+            );
+        }
+    }
+
+    for (ix, chord) in map.chords.iter().enumerate() {
+        chord_warnings(&mut d, &field(&format!("chords[{}]", ix)), chord);
+    }
+    for (ix, layer) in map.layers.iter().enumerate() {
+        let layer_path = field(&format!("layers[{}]", ix));
+        if layer.key == 0 {
+            d.warning(
+                format!("{}.key", layer_path),
+                "the layer has no key, so it can never be active",
+            );
+        } else {
+            d.hotkey(&format!("{}.key", layer_path), layer.key);
+            if let Some(binding) = base.codes().iter().position(|c| *c == layer.key) {
+                d.warning(
+                    format!("{}.key", layer_path),
+                    format!(
+                        "{} is also bound to {}, which it will no longer press",
+                        label_for_code(&layer.key),
+                        BINDING_FIELDS[binding]
+                    ),
+                );
+            }
+        }
+        d.bindings(&format!("{}.bindings", layer_path), &layer.bindings);
+        for (chord_ix, chord) in layer.chords.iter().enumerate() {
+            chord_warnings(
+                &mut d,
+                &format!("{}.chords[{}]", layer_path, chord_ix),
+                chord,
+            );
+        }
+    }
+    d.0
+}
+
+fn chord_warnings(d: &mut Diagnostics, path: &str, chord: &crate::Chord) {
+    if chord.keys.is_empty() {
+        d.warning(format!("{}.keys", path), "the chord has no keys");
+    }
+    if chord.targets.is_empty() {
+        d.warning(format!("{}.targets", path), "the chord drives nothing");
+    }
+    for (ix, &key) in chord.keys.iter().enumerate() {
+        d.hotkey(&format!("{}.keys[{}]", path, ix), key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Game, Layer, Macro};
+
+    fn configuration(controls: ButtonMapping) -> Configuration {
+        let mut configuration = Configuration::default();
+        configuration.games = vec![Game {
+            name: "Test".into(),
+            controls,
+            ..Default::default()
+        }];
+        configuration
+    }
+
+    fn paths(diagnostics: &[Diagnostic], severity: Severity) -> Vec<&str> {
+        diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .map(|d| d.path.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn the_starter_configuration_is_clean() {
+        assert_eq!(validate(&configuration(ButtonMapping::starter())), vec![]);
+    }
+
+    #[test]
+    fn errors_name_the_offending_field() {
+        let mut controls = ButtonMapping::starter();
+        controls.sampling_interval = 0;
+        controls.recoil_impulse_duration = -5;
+        controls.recoil_pattern = vec![RecoilPoint {
+            time: -1,
+            dx: 0,
+            dy: 0,
+        }];
+        let diagnostics = validate(&configuration(controls));
+        assert_eq!(
+            paths(&diagnostics, Severity::Error),
+            vec![
+                "games[0].controls.sampling_interval",
+                "games[0].controls.recoil_impulse_duration",
+                "games[0].controls.recoil_pattern[0].time",
+            ]
+        );
+    }
+
+    #[test]
+    fn games_need_names_of_their_own() {
+        let mut configuration = configuration(ButtonMapping::starter());
+        configuration.games.push(configuration.games[0].clone());
+        assert_eq!(
+            paths(&validate(&configuration), Severity::Error),
+            vec!["games[1].name"]
+        );
+    }
+
+    #[test]
+    fn collisions_are_warnings() {
+        const F5: i32 = 0x74;
+        let mut controls = ButtonMapping::starter();
+        controls.buttonx = F5;
+        // Q is the left shoulder; the later of the two bindings is reported.
+        controls.buttony = controls.shoulderl;
+        controls.macros = vec![Macro {
+            name: "Slide".into(),
+            trigger: controls.buttona,
+            steps: vec![],
+        }];
+        controls.layers = vec![Layer {
+            name: "Alt".into(),
+            key: 0,
+            toggle: false,
+            bindings: KeyBindings::default(),
+            chords: vec![],
+        }];
+        let diagnostics = validate(&configuration(controls));
+        assert_eq!(paths(&diagnostics, Severity::Error), Vec::<&str>::new());
+        assert_eq!(
+            paths(&diagnostics, Severity::Warning),
+            vec![
+                "games[0].controls.buttonx",
+                "games[0].controls.shoulderl",
+                "games[0].controls.macros[0].trigger",
+                "games[0].controls.layers[0].key",
+            ]
+        );
+        assert!(diagnostics[0].to_string().contains("F5"));
+    }
+}
@@ -0,0 +1,74 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Notices when a configuration file changes on disk, by comparing its
+/// modification time with the last one seen. Cheap enough to poll.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    seen: Option<SystemTime>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl ConfigWatcher {
+    pub fn new(path: &Path) -> Self {
+        ConfigWatcher {
+            path: path.to_path_buf(),
+            seen: modified(path),
+        }
+    }
+
+    /// Take the file as it is now as seen, so that our own saves do not
+    /// count as changes.
+    pub fn mark(&mut self) {
+        self.seen = modified(&self.path);
+    }
+
+    /// Whether the file has changed since it was last seen. A file which has
+    /// gone missing has not changed; it is most likely being replaced.
+    pub fn changed(&mut self) -> bool {
+        match modified(&self.path) {
+            Some(now) if Some(now) != self.seen => {
+                self.seen = Some(now);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn touch(path: &Path, secs: u64) {
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn reports_outside_changes_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("configuration.json");
+        std::fs::write(&path, "{}").unwrap();
+        touch(&path, 1_000);
+        let mut watcher = ConfigWatcher::new(&path);
+        assert!(!watcher.changed());
+
+        touch(&path, 2_000);
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        // A change we made ourselves.
+        touch(&path, 3_000);
+        watcher.mark();
+        assert!(!watcher.changed());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(!watcher.changed());
+    }
+}
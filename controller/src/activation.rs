@@ -0,0 +1,228 @@
+use common::{Activation, ActivationMode, GamepadOutput};
+use std::time::Duration;
+
+// How long a short press in long press mode holds the button down for.
+const LONG_PRESS_TAP: Duration = Duration::from_millis(50);
+
+/// What a binding drives for the current report.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+    /// The binding's own button.
+    pub primary: bool,
+    /// The alternate output, during a long press or double tap.
+    pub alternate: Option<GamepadOutput>,
+    /// Whether the outcome will change with time alone.
+    pub animating: bool,
+}
+
+/// Turns the key state behind one binding into button presses according to
+/// its activation mode.
+///
+/// The keyboard hook counts presses as well as tracking whether the key is
+/// down, so a press and release which both land between two ticks still
+/// counts.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Activator {
+    seen: Option<Activation>,
+    presses: u32,
+    latched: bool,
+    pulse_until: Option<Duration>,
+    pressed_at: Option<Duration>,
+    alternate: bool,
+}
+
+impl Activator {
+    pub fn update(
+        &mut self,
+        activation: &Activation,
+        down: bool,
+        presses: u32,
+        now: Duration,
+    ) -> Outcome {
+        // Start afresh whenever the settings change.
+        if self.seen != Some(*activation) {
+            *self = Activator {
+                seen: Some(*activation),
+                presses: self.presses,
+                ..Default::default()
+            };
+        }
+        let new_presses = presses.wrapping_sub(self.presses);
+        self.presses = presses;
+        let pressed = new_presses > 0;
+        let millis = Duration::from_millis(activation.millis.max(0) as u64);
+
+        let mut outcome = Outcome::default();
+        match activation.mode {
+            ActivationMode::Hold => outcome.primary = down || pressed,
+            ActivationMode::Toggle => {
+                if new_presses % 2 == 1 {
+                    self.latched = !self.latched;
+                }
+                outcome.primary = self.latched;
+            }
+            ActivationMode::Tap => {
+                if pressed {
+                    self.pulse_until = Some(now + millis);
+                }
+            }
+            ActivationMode::LongPress => {
+                if pressed {
+                    self.pressed_at = Some(now);
+                    self.alternate = false;
+                }
+                if let Some(at) = self.pressed_at {
+                    if !down {
+                        if !self.alternate {
+                            self.pulse_until = Some(now + LONG_PRESS_TAP);
+                        }
+                        self.pressed_at = None;
+                        self.alternate = false;
+                    } else if now.saturating_sub(at) >= millis {
+                        self.alternate = true;
+                    } else {
+                        outcome.animating = true;
+                    }
+                }
+                if self.alternate {
+                    outcome.alternate(activation.alternate);
+                }
+            }
+            ActivationMode::DoubleTap => {
+                if pressed {
+                    let second = self
+                        .pressed_at
+                        .is_some_and(|at| now.saturating_sub(at) <= millis);
+                    self.alternate = second || new_presses > 1;
+                    // After a double tap the next press starts a new pair.
+                    self.pressed_at = if self.alternate { None } else { Some(now) };
+                }
+                if down || pressed {
+                    if self.alternate {
+                        outcome.alternate(activation.alternate);
+                    } else {
+                        outcome.primary = true;
+                    }
+                }
+            }
+        }
+
+        if self.pulse_until.is_some_and(|until| now < until) {
+            outcome.primary = true;
+            outcome.animating = true;
+        }
+        outcome
+    }
+}
+
+impl Outcome {
+    // Without an alternate output configured the binding's own button stands in.
+    fn alternate(&mut self, alternate: Option<GamepadOutput>) {
+        match alternate {
+            Some(output) => self.alternate = Some(output),
+            None => self.primary = true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn activation(mode: ActivationMode) -> Activation {
+        Activation {
+            mode,
+            millis: 200,
+            alternate: Some(GamepadOutput::Y),
+        }
+    }
+
+    #[test]
+    fn hold_still_reports_a_press_shorter_than_a_tick() {
+        let hold = activation(ActivationMode::Hold);
+        let mut activator = Activator::default();
+        assert!(!activator.update(&hold, false, 0, ms(0)).primary);
+        // Pressed and released again before the controller looked.
+        assert!(activator.update(&hold, false, 1, ms(2)).primary);
+        assert!(!activator.update(&hold, false, 1, ms(4)).primary);
+    }
+
+    #[test]
+    fn toggle_latches_on_each_press() {
+        let toggle = activation(ActivationMode::Toggle);
+        let mut activator = Activator::default();
+        assert!(activator.update(&toggle, true, 1, ms(0)).primary);
+        assert!(activator.update(&toggle, false, 1, ms(100)).primary);
+        assert!(!activator.update(&toggle, true, 2, ms(200)).primary);
+        // Two presses between ticks cancel out.
+        assert!(!activator.update(&toggle, false, 4, ms(300)).primary);
+    }
+
+    #[test]
+    fn tap_gives_a_fixed_length_press() {
+        let tap = activation(ActivationMode::Tap);
+        let mut activator = Activator::default();
+        let outcome = activator.update(&tap, true, 1, ms(0));
+        assert!(outcome.primary && outcome.animating);
+        assert!(activator.update(&tap, true, 1, ms(199)).primary);
+        assert!(!activator.update(&tap, true, 1, ms(200)).primary);
+    }
+
+    #[test]
+    fn long_press_switches_to_the_alternate_output() {
+        let long = activation(ActivationMode::LongPress);
+        let mut activator = Activator::default();
+
+        // Short press: nothing until release, then a tap of the button itself.
+        let outcome = activator.update(&long, true, 1, ms(0));
+        assert_eq!((outcome.primary, outcome.animating), (false, true));
+        assert!(activator.update(&long, false, 1, ms(150)).primary);
+        assert!(!activator.update(&long, false, 1, ms(200)).primary);
+
+        // Long press: the alternate output, held until release.
+        activator.update(&long, true, 2, ms(1000));
+        assert_eq!(
+            activator.update(&long, true, 2, ms(1200)).alternate,
+            Some(GamepadOutput::Y)
+        );
+        assert_eq!(
+            activator.update(&long, false, 2, ms(1500)),
+            Outcome::default()
+        );
+    }
+
+    #[test]
+    fn double_tap_holds_the_alternate_output() {
+        let double = activation(ActivationMode::DoubleTap);
+        let mut activator = Activator::default();
+        assert!(activator.update(&double, true, 1, ms(0)).primary);
+        assert!(!activator.update(&double, false, 1, ms(50)).primary);
+        let outcome = activator.update(&double, true, 2, ms(150));
+        assert_eq!(
+            (outcome.primary, outcome.alternate),
+            (false, Some(GamepadOutput::Y))
+        );
+        activator.update(&double, false, 2, ms(200));
+        // A third press in quick succession starts a new pair.
+        assert!(activator.update(&double, true, 3, ms(250)).primary);
+
+        // Too slow for a double tap.
+        assert!(activator.update(&double, true, 4, ms(900)).primary);
+    }
+
+    #[test]
+    fn missing_alternate_falls_back_to_the_button_itself() {
+        let long = Activation {
+            alternate: None,
+            ..activation(ActivationMode::LongPress)
+        };
+        let mut activator = Activator::default();
+        activator.update(&long, true, 1, ms(0));
+        let outcome = activator.update(&long, true, 1, ms(300));
+        assert_eq!((outcome.primary, outcome.alternate), (true, None));
+    }
+}
@@ -0,0 +1,364 @@
+use common::{Chord, Layer};
+
+/// Resolves keyboard events into presses and releases of the controller's
+/// key bindings, numbered as in `common::KeyBindings::codes`.
+///
+/// When a key goes down it is looked up as follows, and the first rule that
+/// finds anything decides what the key drives:
+///
+/// 1. Each active layer, from the last to the first, then the base bindings.
+///    A layer which binds the key in any way hides everything beneath it.
+/// 2. Within a layer, of the bindings triggered by the key (plain bindings
+///    count as chords of one key) only those with the most keys apply, so
+///    Shift+E beats E. A chord only triggers on its last key, with the keys
+///    before it already held.
+/// 3. Every binding left applies at once: several keys can drive the same
+///    button, and one key can drive several buttons.
+///
+/// Once down a key keeps driving whatever it resolved to until it is
+/// released, even if layers or modifiers change in between, so nothing is
+/// left stuck down. A button stays down while any key drives it.
+#[derive(Debug, Default)]
+pub struct KeyResolver {
+    // The base bindings' keys, the chords and the layers, as last configured.
+    base: Vec<i32>,
+    chords: Vec<Chord>,
+    layers: Vec<Layer>,
+    // The keys of the toggled layers which are switched on.
+    toggled: Vec<i32>,
+    // Every key currently down, whether ours or passed on.
+    held: Vec<i32>,
+    // Keys currently down which we swallowed, and the bindings each one
+    // drives. Layer keys are kept here too, driving nothing.
+    down: Vec<(i32, Vec<usize>)>,
+    // How many keys currently drive each binding.
+    drivers: Vec<u32>,
+}
+
+impl KeyResolver {
+    pub const fn new() -> Self {
+        KeyResolver {
+            base: Vec::new(),
+            chords: Vec::new(),
+            layers: Vec::new(),
+            toggled: Vec::new(),
+            held: Vec::new(),
+            down: Vec::new(),
+            drivers: Vec::new(),
+        }
+    }
+
+    /// Replace the bindings. Keys already down keep driving what they did,
+    /// and toggled layers stay on as long as there is still one with their
+    /// key.
+    pub fn configure(&mut self, base: &[i32], chords: &[Chord], layers: &[Layer]) {
+        self.base = base.to_vec();
+        self.chords = chords.to_vec();
+        self.layers = layers.to_vec();
+        self.toggled
+            .retain(|key| layers.iter().any(|layer| layer.toggle && layer.key == *key));
+        self.drivers.resize(base.len(), 0);
+    }
+
+    pub fn chords(&self) -> &[Chord] {
+        &self.chords
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    /// Handle a key event. Returns `None` for keys which are not ours, which
+    /// should be passed on, and otherwise the bindings whose state changed.
+    pub fn key(&mut self, code: i32, down: bool) -> Option<Vec<(usize, bool)>> {
+        if code == 0 {
+            return None;
+        }
+
+        let repeat = self.held.contains(&code);
+        if !down {
+            self.held.retain(|key| *key != code);
+            let ours = self.down.iter().position(|(key, _)| *key == code)?;
+            let (_, bindings) = self.down.remove(ours);
+            return Some(self.drive(&bindings, false));
+        }
+        if repeat {
+            return self.down.iter().any(|(key, _)| *key == code).then(Vec::new);
+        }
+
+        let bindings = if let Some(layer) = self.layers.iter().find(|layer| layer.key == code) {
+            if layer.toggle {
+                match self.toggled.iter().position(|key| *key == code) {
+                    Some(on) => _ = self.toggled.remove(on),
+                    None => self.toggled.push(code),
+                }
+            }
+            vec![]
+        } else {
+            let bindings = self.lookup(code);
+            if bindings.is_empty() {
+                self.held.push(code);
+                return None;
+            }
+            bindings
+        };
+        let changes = self.drive(&bindings, true);
+        self.held.push(code);
+        self.down.push((code, bindings));
+        Some(changes)
+    }
+
+    fn active(&self, layer: &Layer) -> bool {
+        if layer.toggle {
+            self.toggled.contains(&layer.key)
+        } else {
+            self.held.contains(&layer.key)
+        }
+    }
+
+    fn lookup(&self, code: i32) -> Vec<usize> {
+        for layer in self.layers.iter().rev() {
+            if self.active(layer) {
+                let bound = self.triggered(&layer.bindings.codes(), &layer.chords, code);
+                if !bound.is_empty() {
+                    return bound;
+                }
+            }
+        }
+        self.triggered(&self.base, &self.chords, code)
+    }
+
+    // The bindings `code` triggers among one layer's plain bindings and chords.
+    fn triggered(&self, codes: &[i32], chords: &[Chord], code: i32) -> Vec<usize> {
+        let mut matches: Vec<(usize, usize)> = codes
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c == code)
+            .map(|(ix, _)| (1, ix))
+            .collect();
+        for chord in chords {
+            let Some((last, modifiers)) = chord.keys.split_last() else {
+                continue;
+            };
+            if *last == code && modifiers.iter().all(|key| self.held.contains(key)) {
+                matches.extend(
+                    chord
+                        .targets
+                        .iter()
+                        .map(|target| (chord.keys.len(), target.index())),
+                );
+            }
+        }
+        let longest = matches.iter().map(|(keys, _)| *keys).max().unwrap_or(0);
+        let mut bound: Vec<usize> = matches
+            .into_iter()
+            .filter(|(keys, _)| *keys == longest)
+            .map(|(_, binding)| binding)
+            .collect();
+        bound.sort_unstable();
+        bound.dedup();
+        bound
+    }
+
+    fn drive(&mut self, bindings: &[usize], down: bool) -> Vec<(usize, bool)> {
+        let mut changes = vec![];
+        for &binding in bindings {
+            let Some(drivers) = self.drivers.get_mut(binding) else {
+                continue;
+            };
+            if down {
+                *drivers += 1;
+                if *drivers == 1 {
+                    changes.push((binding, true));
+                }
+            } else if *drivers > 0 {
+                *drivers -= 1;
+                if *drivers == 0 {
+                    changes.push((binding, false));
+                }
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{BindingTarget, KeyBindings};
+
+    const Q: i32 = 0x51;
+    const E: i32 = 0x45;
+    const F: i32 = 0x46;
+    const SHIFT: i32 = 0xa0;
+    const CTRL: i32 = 0xa2;
+
+    // Base bindings: E is start (0) and Q is button A (5).
+    fn base() -> [i32; 19] {
+        KeyBindings {
+            start: E,
+            buttona: Q,
+            ..Default::default()
+        }
+        .codes()
+    }
+
+    // Holding shift turns E into button B (6); tapping control toggles a layer
+    // which turns Q into button X (7) and E into button Y (8).
+    fn layers() -> Vec<Layer> {
+        vec![
+            Layer {
+                name: "Shift".into(),
+                key: SHIFT,
+                toggle: false,
+                bindings: KeyBindings {
+                    buttonb: E,
+                    ..Default::default()
+                },
+                chords: vec![],
+            },
+            Layer {
+                name: "Control".into(),
+                key: CTRL,
+                toggle: true,
+                bindings: KeyBindings {
+                    buttonx: Q,
+                    buttony: E,
+                    ..Default::default()
+                },
+                chords: vec![],
+            },
+        ]
+    }
+
+    fn layered() -> KeyResolver {
+        let mut resolver = KeyResolver::default();
+        resolver.configure(&base(), &[], &layers());
+        resolver
+    }
+
+    #[test]
+    fn held_layer_overrides_and_falls_through() {
+        let mut resolver = layered();
+        assert_eq!(resolver.key(E, true), Some(vec![(0, true)]));
+        assert_eq!(resolver.key(E, false), Some(vec![(0, false)]));
+
+        assert_eq!(resolver.key(SHIFT, true), Some(vec![]));
+        assert_eq!(resolver.key(E, true), Some(vec![(6, true)]));
+        // Q is not bound in the shift layer, so the base binding applies.
+        assert_eq!(resolver.key(Q, true), Some(vec![(5, true)]));
+        assert_eq!(resolver.key(SHIFT, false), Some(vec![]));
+        assert_eq!(resolver.key(Q, false), Some(vec![(5, false)]));
+    }
+
+    #[test]
+    fn keys_release_what_they_pressed_after_the_layer_changes() {
+        let mut resolver = layered();
+        resolver.key(SHIFT, true);
+        assert_eq!(resolver.key(E, true), Some(vec![(6, true)]));
+        resolver.key(SHIFT, false);
+        // Key repeat does not re-resolve.
+        assert_eq!(resolver.key(E, true), Some(vec![]));
+        assert_eq!(resolver.key(E, false), Some(vec![(6, false)]));
+    }
+
+    #[test]
+    fn toggled_layer_stays_until_toggled_off_and_later_layers_win() {
+        let mut resolver = layered();
+        resolver.key(CTRL, true);
+        resolver.key(CTRL, false);
+        assert_eq!(resolver.key(Q, true), Some(vec![(7, true)]));
+        resolver.key(Q, false);
+
+        // Both layers bind E; the control layer comes later so it wins.
+        resolver.key(SHIFT, true);
+        assert_eq!(resolver.key(E, true), Some(vec![(8, true)]));
+        resolver.key(E, false);
+        resolver.key(SHIFT, false);
+
+        resolver.key(CTRL, true);
+        resolver.key(CTRL, false);
+        assert_eq!(resolver.key(Q, true), Some(vec![(5, true)]));
+    }
+
+    #[test]
+    fn unbound_keys_are_passed_on() {
+        let mut resolver = layered();
+        assert_eq!(resolver.key(F, true), None);
+        assert_eq!(resolver.key(F, false), None);
+        assert_eq!(resolver.key(0, true), None);
+    }
+
+    fn chord(keys: &[i32], targets: &[BindingTarget]) -> Chord {
+        Chord {
+            keys: keys.to_vec(),
+            targets: targets.to_vec(),
+        }
+    }
+
+    // Base bindings plus: Shift+E is Y, F is an extra key for A, and Q also
+    // drives Back.
+    fn chorded() -> KeyResolver {
+        let chords = [
+            chord(&[SHIFT, E], &[BindingTarget::Y]),
+            chord(&[F], &[BindingTarget::A]),
+            chord(&[Q], &[BindingTarget::Back]),
+        ];
+        let mut resolver = layered();
+        resolver.configure(&base(), &chords, &[]);
+        resolver
+    }
+
+    #[test]
+    fn chords_beat_plain_bindings_only_with_modifiers_held_first() {
+        let mut resolver = chorded();
+        // Shift is not bound to anything itself, so the game still sees it.
+        assert_eq!(resolver.key(SHIFT, true), None);
+        assert_eq!(resolver.key(E, true), Some(vec![(8, true)]));
+        assert_eq!(resolver.key(SHIFT, false), None);
+        assert_eq!(resolver.key(E, false), Some(vec![(8, false)]));
+
+        // Pressed the other way round, E is just E.
+        assert_eq!(resolver.key(E, true), Some(vec![(0, true)]));
+        assert_eq!(resolver.key(SHIFT, true), None);
+    }
+
+    #[test]
+    fn alternative_keys_hold_a_button_until_both_are_released() {
+        let mut resolver = chorded();
+        assert_eq!(resolver.key(F, true), Some(vec![(5, true)]));
+        // Q drives A along with Back, its extra target.
+        assert_eq!(resolver.key(Q, true), Some(vec![(13, true)]));
+        assert_eq!(resolver.key(F, false), Some(vec![]));
+        assert_eq!(resolver.key(Q, false), Some(vec![(5, false), (13, false)]));
+    }
+
+    #[test]
+    fn layer_precedence_applies_before_chord_length() {
+        let mut resolver = KeyResolver::default();
+        let chords = [chord(&[SHIFT, E], &[BindingTarget::Back])];
+        resolver.configure(&base(), &chords, &layers());
+        // The shift layer binds E plainly, which hides the base layer's chord.
+        resolver.key(SHIFT, true);
+        assert_eq!(resolver.key(E, true), Some(vec![(6, true)]));
+    }
+
+    #[test]
+    fn toggles_follow_their_layer_when_the_layers_change() {
+        let mut resolver = layered();
+        resolver.key(CTRL, true);
+        resolver.key(CTRL, false);
+
+        // The control layer moving first keeps it toggled on...
+        let mut reordered = layers();
+        reordered.reverse();
+        resolver.configure(&base(), &[], &reordered);
+        assert_eq!(resolver.key(Q, true), Some(vec![(7, true)]));
+        resolver.key(Q, false);
+
+        // ...but once it is gone, a new layer on the same key starts off.
+        resolver.configure(&base(), &[], &layers()[..1]);
+        resolver.configure(&base(), &[], &layers());
+        assert_eq!(resolver.key(Q, true), Some(vec![(5, true)]));
+    }
+}
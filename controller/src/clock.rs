@@ -0,0 +1,176 @@
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// A monotonic time source, measured as the time elapsed since a fixed origin.
+/// Abstracted so that anything timing dependent can be driven from tests.
+pub trait Clock {
+    fn now(&self) -> Duration;
+    /// Block the calling thread until `deadline`, or indefinitely if there is
+    /// none. May return early when the thread is woken by an input event.
+    fn park(&self, deadline: Option<Duration>);
+}
+
+static ORIGIN: OnceLock<Instant> = OnceLock::new();
+
+/// The process wide monotonic clock, shared by the input hooks and the
+/// controller thread so that instants recorded in one are comparable in the other.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        ORIGIN.get_or_init(Instant::now).elapsed()
+    }
+
+    fn park(&self, deadline: Option<Duration>) {
+        match deadline {
+            Some(deadline) => {
+                let now = self.now();
+                if deadline > now {
+                    std::thread::park_timeout(deadline - now);
+                }
+            }
+            None => std::thread::park(),
+        }
+    }
+}
+
+/// Converts a recorded instant (in microseconds) into time elapsed since then.
+/// Instants from the future, e.g. written by a hook after `now` was sampled,
+/// count as zero rather than underflowing.
+pub fn elapsed_since(instant_micros: u64, now: Duration) -> Duration {
+    now.saturating_sub(Duration::from_micros(instant_micros))
+}
+
+/// Decides when the controller loop next emits a report.
+///
+/// While something time dependent is going on (mouse movement, autofire, a
+/// recoil impulse) reports go out at a fixed rate. Each deadline is the
+/// previous deadline plus the interval rather than the end of the previous
+/// tick plus the interval, so time spent doing work within a tick does not
+/// accumulate as drift; if the loop falls behind by more than an interval it
+/// resynchronises instead of bursting to catch up. Otherwise the loop sleeps
+/// until an input event wakes it. Being woken early never moves the periodic
+/// schedule.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    deadline: Option<Duration>,
+}
+
+impl Scheduler {
+    /// Returns true if the periodic tick was overrun and had to resynchronise.
+    pub fn wait<C: Clock>(&mut self, clock: &C, interval: Duration, periodic: bool) -> bool {
+        if !periodic {
+            self.deadline = None;
+            clock.park(None);
+            return false;
+        }
+
+        let now = clock.now();
+        let mut deadline = self.deadline.unwrap_or(now + interval);
+        let overrun = deadline < now;
+        if overrun {
+            deadline = now;
+        }
+        clock.park(Some(deadline));
+        self.deadline = if clock.now() >= deadline {
+            Some(deadline + interval)
+        } else {
+            Some(deadline)
+        };
+        overrun
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use super::Clock;
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    /// A clock which only moves when told to, or when slept on.
+    #[derive(Debug, Default)]
+    pub struct MockClock {
+        now: Cell<Duration>,
+    }
+
+    impl MockClock {
+        pub fn advance(&self, by: Duration) {
+            self.now.set(self.now.get() + by);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Duration {
+            self.now.get()
+        }
+
+        // Nothing ever wakes a mock clock early.
+        fn park(&self, deadline: Option<Duration>) {
+            if let Some(deadline) = deadline {
+                if deadline > self.now.get() {
+                    self.now.set(deadline);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::MockClock;
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_micros(2000);
+
+    #[test]
+    fn work_within_a_tick_does_not_cause_drift() {
+        let clock = MockClock::default();
+        let mut scheduler = Scheduler::default();
+        for tick in 1..=100u32 {
+            assert!(!scheduler.wait(&clock, INTERVAL, true));
+            assert_eq!(clock.now(), INTERVAL * tick);
+            clock.advance(Duration::from_micros(700));
+        }
+    }
+
+    #[test]
+    fn overrun_resynchronises_rather_than_bursting() {
+        let clock = MockClock::default();
+        let mut scheduler = Scheduler::default();
+        scheduler.wait(&clock, INTERVAL, true);
+        clock.advance(INTERVAL * 3);
+        assert!(scheduler.wait(&clock, INTERVAL, true));
+        assert_eq!(clock.now(), INTERVAL * 4);
+        assert!(!scheduler.wait(&clock, INTERVAL, true));
+        assert_eq!(clock.now(), INTERVAL * 5);
+    }
+
+    #[test]
+    fn idle_waits_for_an_event_and_then_starts_a_fresh_period() {
+        let clock = MockClock::default();
+        let mut scheduler = Scheduler::default();
+        scheduler.wait(&clock, INTERVAL, true);
+        scheduler.wait(&clock, INTERVAL, false);
+        assert_eq!(clock.now(), INTERVAL);
+
+        clock.advance(Duration::from_micros(12345));
+        scheduler.wait(&clock, INTERVAL, true);
+        assert_eq!(
+            clock.now(),
+            INTERVAL + Duration::from_micros(12345) + INTERVAL
+        );
+    }
+
+    #[test]
+    fn instants_in_the_future_do_not_underflow() {
+        assert_eq!(
+            elapsed_since(5000, Duration::from_millis(2)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            elapsed_since(1000, Duration::from_millis(3)),
+            Duration::from_millis(2)
+        );
+    }
+}
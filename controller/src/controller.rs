@@ -1,122 +1,331 @@
+use crate::activation::Activator;
+use crate::clock::{elapsed_since, Clock, Scheduler, SystemClock};
+use crate::firing::in_recoil_impulse;
+use crate::macros::MacroPlayer;
+use crate::metrics;
+use crate::movement::{scale, MovementShaper};
+use crate::recoil::{compensate, compensation_active};
+use crate::script::{Input, Report, Script};
+use crate::socd::SocdAxis;
 use crate::statics::*;
+use crate::stick::apply_gate;
+use crate::turbo::Turbo;
 use log::info;
 use std::sync::atomic::Ordering;
+use std::time::Duration;
 use vigem_client::*;
 
 pub fn run_controller(mut gamepad: XGamepad, mut target: Xbox360Wired<Client>) {
     info!("Launching serf controller.");
 
     info!("Virtual gamepad attached.");
+    let mut horizontal = SocdAxis::default();
+    let mut vertical = SocdAxis::default();
+    let mut movement = MovementShaper::default();
+    let mut activators = [Activator::default(); 14];
+    let mut turbo = [Turbo::default(); 14];
+    let mut macros: Vec<MacroPlayer> = vec![];
+    let mut script = Script::new();
+    let mut script_error: Option<String> = None;
+    let clock = SystemClock;
+    let mut scheduler = Scheduler::default();
+    _ = CONTROLLER_THREAD.set(std::thread::current());
+
+    // Whether the report will change with time alone, so the next one must be
+    // produced by the periodic tick rather than waiting for an input event.
+    let mut periodic = false;
     loop {
-        std::thread::sleep(std::time::Duration::from_micros(
-            INTERVAL_MICROS.load(Ordering::Relaxed),
-        ));
-        let multiplier = MOVEMENT_MULTIPLIER.load(Ordering::Relaxed);
-        let thumb_rx = i16::saturating_mul(X.swap(0, Ordering::Relaxed) as i16, multiplier);
-        let thumb_ry = i16::saturating_mul(-1 * Y.swap(0, Ordering::Relaxed) as i16, multiplier);
+        let overrun = scheduler.wait(
+            &clock,
+            Duration::from_micros(INTERVAL_MICROS.load(Ordering::Relaxed)),
+            periodic,
+        );
+        if overrun {
+            metrics::METRICS
+                .lock()
+                .expect("metrics lock poisoned")
+                .overrun();
+        }
+        let armament = armament();
+        let gate = STICK_GATE.load(Ordering::Relaxed).into();
+        let mut multiplier = MOVEMENT_MULTIPLIER.load(Ordering::Relaxed) as i32;
+        if RBUTTONDOWN.load(Ordering::Relaxed) {
+            multiplier = multiplier.saturating_mul(armament.ads_sensitivity.clamp(0, 1000)) / 100;
+        }
+        let (mouse_x, mouse_y) = (
+            X.swap(0, Ordering::Relaxed),
+            -1 * Y.swap(0, Ordering::Relaxed),
+        );
+        let (mouse_rx, mouse_ry) = (
+            i32::saturating_mul(mouse_x, multiplier),
+            i32::saturating_mul(mouse_y, multiplier),
+        );
+        periodic = mouse_rx != 0 || mouse_ry != 0;
+
+        let now = clock.now();
+
+        // Recoil compensation adjusts the gamepad stick position by a given
+        // percentage. It is added to the mouse motion before the stick is
+        // gated, so that it is not lost when the mouse pins the stick.
+        let (mut raw_rx, mut raw_ry) = (mouse_rx, mouse_ry);
+        if LBUTTONDOWN.load(Ordering::Relaxed)
+            && RECOIL_COMPENSATION_ACTIVE.load(Ordering::Relaxed)
+            && compensation_active(
+                RECOIL_ACTIVATION.load(Ordering::Relaxed).into(),
+                RBUTTONDOWN.load(Ordering::Relaxed),
+                RECOIL_MODIFIER.load(Ordering::Relaxed),
+            )
+        {
+            let delta = elapsed_since(LEFT_DOWN_INSTANT.load(Ordering::Relaxed), now);
+            let mut dx = RECOIL_COMPENSATION_SIDEWAYS.load(Ordering::Relaxed) as f64;
+            let mut dy = RECOIL_COMPENSATION_VERTICAL.load(Ordering::Relaxed) as f64;
+            if in_recoil_impulse(delta, RECOIL_IMPULSE_DURATION.load(Ordering::Relaxed)) {
+                dy += RECOIL_IMPULSE_VERTICAL.load(Ordering::Relaxed) as f64;
+                periodic = true;
+            }
+
+            let pattern = &armament.recoil_pattern;
+            let (px, py) = pattern.offset(delta);
+            periodic |= pattern.running(delta);
+            (raw_rx, raw_ry) = compensate((raw_rx, raw_ry), (dx + px, dy + py));
+        }
+        let (thumb_rx, thumb_ry) = apply_gate(gate, raw_rx, raw_ry);
 
         gamepad.thumb_rx = thumb_rx;
         gamepad.thumb_ry = thumb_ry;
-
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .expect("Cant get systemtime")
-            .as_millis() as u64;
+        if mouse_rx != 0 || mouse_ry != 0 {
+            let clipped =
+                (raw_rx as f64).hypot(raw_ry as f64) - (thumb_rx as f64).hypot(thumb_ry as f64);
+            let dropped = if multiplier != 0 && clipped > 1. {
+                (clipped / multiplier.abs() as f64).round() as u64
+            } else {
+                0
+            };
+            metrics::METRICS
+                .lock()
+                .expect("metrics lock poisoned")
+                .motion(dropped);
+        }
 
         if RBUTTONDOWN.load(Ordering::Relaxed) {
-            let delta = now - RIGHT_DOWN_INSTANT.load(Ordering::Relaxed);
-
-            if RIGHT_AUTOFIRE.load(Ordering::Relaxed) {
-                // 37 ms on, 37 off gives circa 800 RPM.
-                if delta % 74 < 37 {
-                    gamepad.left_trigger = 255;
-                } else {
-                    gamepad.left_trigger = 0;
-                }
-            } else {
-                gamepad.left_trigger = 255;
-            }
+            let delta = elapsed_since(RIGHT_DOWN_INSTANT.load(Ordering::Relaxed), now);
+            let trigger = left_trigger();
+            gamepad.left_trigger = trigger.value(delta);
+            periodic |= trigger.animating(delta);
         } else {
             gamepad.left_trigger = 0;
         }
         if LBUTTONDOWN.load(Ordering::Relaxed) {
-            let delta = now - LEFT_DOWN_INSTANT.load(Ordering::Relaxed);
-            // Recoil compensation adjusts the gamepad stick position by a given percentage
-            if RECOIL_COMPENSATION_ACTIVE.load(Ordering::Relaxed)
-                && RBUTTONDOWN.load(Ordering::Relaxed)
-            {
-                gamepad.thumb_rx = gamepad.thumb_rx.saturating_add(
-                    i16::MAX / 100 * RECOIL_COMPENSATION_SIDEWAYS.load(Ordering::Relaxed) as i16,
-                );
-
-                gamepad.thumb_ry = gamepad.thumb_ry.saturating_add(
-                    i16::MAX / 100 * RECOIL_COMPENSATION_VERTICAL.load(Ordering::Relaxed) as i16,
-                );
-                if delta < RECOIL_IMPULSE_DURATION.load(Ordering::Relaxed) as u64 {
-                    gamepad.thumb_ry = gamepad.thumb_ry.saturating_add(
-                        i16::MAX / 100 * RECOIL_IMPULSE_VERTICAL.load(Ordering::Relaxed) as i16,
-                    );
-                }
-            }
-
-            // Autofire
-            if LEFT_AUTOFIRE.load(Ordering::Relaxed) {
-                // 37 ms on, 37 off gives circa 800 RPM.
-                if delta % 74 < 37 {
-                    gamepad.right_trigger = 255;
-                } else {
-                    gamepad.right_trigger = 0;
-                }
-            } else {
-                gamepad.right_trigger = 255;
-            }
+            let delta = elapsed_since(LEFT_DOWN_INSTANT.load(Ordering::Relaxed), now);
+            let trigger = armament.right_trigger;
+            gamepad.right_trigger = trigger.value(delta);
+            periodic |= trigger.animating(delta);
         } else {
             gamepad.right_trigger = 0;
         }
 
         gamepad.buttons = XButtons::default();
 
-        let button_map = vec![
-            (&START, XButtons::START),
-            (&DPADUP, XButtons::UP),
-            (&DPADDOWN, XButtons::DOWN),
-            (&DPADLEFT, XButtons::LEFT),
-            (&DPADRIGHT, XButtons::RIGHT),
-            (&BUTTONA, XButtons::A),
-            (&BUTTONB, XButtons::B),
-            (&BUTTONX, XButtons::X),
-            (&BUTTONY, XButtons::Y),
-            (&SHOULDER_L, XButtons::LB),
-            (&SHOULDER_R, XButtons::RB),
-            (&THUMB_L, XButtons::LTHUMB),
-            (&THUMB_R, XButtons::RTHUMB),
-            (&BACK, XButtons::BACK),
+        let activations = *ACTIVATIONS.lock().expect("activations lock poisoned");
+        let button_map = [
+            (&START, &TURBO_START, activations.start, XButtons::START),
+            (&DPADUP, &TURBO_DPAD_U, activations.dpadu, XButtons::UP),
+            (&DPADDOWN, &TURBO_DPAD_D, activations.dpadd, XButtons::DOWN),
+            (&DPADLEFT, &TURBO_DPAD_L, activations.dpadl, XButtons::LEFT),
+            (
+                &DPADRIGHT,
+                &TURBO_DPAD_R,
+                activations.dpadr,
+                XButtons::RIGHT,
+            ),
+            (&BUTTONA, &TURBO_BUTTON_A, activations.buttona, XButtons::A),
+            (&BUTTONB, &TURBO_BUTTON_B, activations.buttonb, XButtons::B),
+            (&BUTTONX, &TURBO_BUTTON_X, activations.buttonx, XButtons::X),
+            (&BUTTONY, &TURBO_BUTTON_Y, activations.buttony, XButtons::Y),
+            (
+                &SHOULDER_L,
+                &TURBO_SHOULDER_L,
+                activations.shoulderl,
+                XButtons::LB,
+            ),
+            (
+                &SHOULDER_R,
+                &TURBO_SHOULDER_R,
+                activations.shoulderr,
+                XButtons::RB,
+            ),
+            (
+                &THUMB_L,
+                &TURBO_THUMB_L,
+                activations.lthumb,
+                XButtons::LTHUMB,
+            ),
+            (
+                &THUMB_R,
+                &TURBO_THUMB_R,
+                activations.rthumb,
+                XButtons::RTHUMB,
+            ),
+            (&BACK, &TURBO_BACK, activations.back, XButtons::BACK),
         ];
-        for (is_pressed, button) in button_map {
-            if is_pressed.load(Ordering::Relaxed) {
+        let mut alternates = vec![];
+        for ((key, rate, activation, button), (activator, turbo)) in button_map
+            .into_iter()
+            .zip(activators.iter_mut().zip(turbo.iter_mut()))
+        {
+            let outcome = activator.update(&activation, key.is_down(), key.presses(), now);
+            periodic |= outcome.animating;
+            alternates.extend(outcome.alternate);
+            let (down, repeating) =
+                turbo.update(outcome.primary, rate.load(Ordering::Relaxed), now);
+            periodic |= repeating;
+            if down {
                 gamepad.buttons.raw = gamepad.buttons.raw | button;
             }
         }
+        for output in alternates {
+            press_output(&mut gamepad, output);
+        }
 
-        // Left thumbstick. Why is X backwards?
-        if LSTICKUP.load(Ordering::Relaxed) {
-            gamepad.thumb_ly = i16::MAX;
-        } else if LSTICKDOWN.load(Ordering::Relaxed) {
-            gamepad.thumb_ly = i16::MIN;
-        } else {
-            gamepad.thumb_ly = 0;
+        if CANCEL_MACROS.swap(false, Ordering::Relaxed) {
+            macros.iter_mut().for_each(MacroPlayer::cancel);
+        }
+        for ix in MACRO_REQUESTS
+            .lock()
+            .expect("macro requests lock poisoned")
+            .drain(..)
+        {
+            if let Some(m) = MACROS.lock().expect("macros lock poisoned").get(ix) {
+                if macros.len() <= ix {
+                    macros.resize_with(ix + 1, MacroPlayer::default);
+                }
+                macros[ix].trigger(&m.steps, now);
+            }
+        }
+        for player in macros.iter_mut() {
+            player.update(now);
+            periodic |= player.running();
+            for output in player.held() {
+                press_output(&mut gamepad, *output);
+            }
         }
 
-        if LSTICKRIGHT.load(Ordering::Relaxed) {
-            gamepad.thumb_lx = i16::MIN;
-        } else if LSTICKLEFT.load(Ordering::Relaxed) {
-            gamepad.thumb_lx = i16::MAX;
-        } else {
-            gamepad.thumb_lx = 0;
+        // Left thumbstick. Why is X backwards?
+        let thumb_ly = vertical.resolve(
+            SOCD_VERTICAL.load(Ordering::Relaxed).into(),
+            LSTICKDOWN.is_down(),
+            LSTICKUP.is_down(),
+        ) * i16::MAX as i32;
+        let thumb_lx = horizontal.resolve(
+            SOCD_HORIZONTAL.load(Ordering::Relaxed).into(),
+            LSTICKRIGHT.is_down(),
+            LSTICKLEFT.is_down(),
+        ) * i16::MAX as i32;
+        let magnitude = movement.magnitude(
+            thumb_lx != 0 || thumb_ly != 0,
+            WALK.is_down(),
+            WALK_SPEED.load(Ordering::Relaxed),
+            MOVEMENT_RAMP.load(Ordering::Relaxed),
+            now,
+        );
+        (gamepad.thumb_lx, gamepad.thumb_ly) =
+            scale(apply_gate(gate, thumb_lx, thumb_ly), magnitude);
+        periodic |= movement.ramping();
+
+        // The script has the last word on the report. It runs with every
+        // report, and keeps the controller ticking only while it asks to.
+        script.load(&SCRIPT.lock().expect("script lock poisoned"));
+        let events: Vec<(i32, bool)> = SCRIPT_EVENTS
+            .lock()
+            .expect("script events lock poisoned")
+            .drain(..)
+            .collect();
+        if script.running() {
+            let input = Input {
+                bindings: BINDINGS.map(|(_, key)| key.is_down()),
+                mouse: (mouse_x, mouse_y),
+                firing: LBUTTONDOWN.load(Ordering::Relaxed),
+                aiming: RBUTTONDOWN.load(Ordering::Relaxed),
+                now,
+                held: vec![],
+            };
+            let mut report = script_report(&gamepad);
+            script.run(&input, &mut report, &events);
+            apply_script_report(&mut gamepad, &report);
+            periodic |= script.ticking();
+        }
+        if script.error() != script_error.as_deref() {
+            script_error = script.error().map(str::to_string);
+            *SCRIPT_ERROR.lock().expect("script error lock poisoned") =
+                Some(script_error.clone().unwrap_or_default());
+            crate::script_error_changed();
         }
 
         target
             .update(&gamepad)
             .expect("should be able to update our gamepad");
+
+        let sent = clock.now();
+        let latency = match PENDING_INPUT_INSTANT.swap(0, Ordering::Relaxed) {
+            0 => None,
+            instant => Some(elapsed_since(instant, sent)),
+        };
+        metrics::METRICS
+            .lock()
+            .expect("metrics lock poisoned")
+            .report(sent, latency);
+    }
+}
+
+fn press_output(gamepad: &mut XGamepad, output: common::GamepadOutput) {
+    match output {
+        common::GamepadOutput::LeftTrigger => gamepad.left_trigger = 255,
+        common::GamepadOutput::RightTrigger => gamepad.right_trigger = 255,
+        button => gamepad.buttons.raw |= xbutton(button),
+    }
+}
+
+fn script_report(gamepad: &XGamepad) -> Report {
+    Report {
+        buttons: common::GamepadOutput::ALL
+            .into_iter()
+            .filter(|output| gamepad.buttons.raw & xbutton(*output) != 0)
+            .collect(),
+        left_trigger: gamepad.left_trigger,
+        right_trigger: gamepad.right_trigger,
+        left_stick: (gamepad.thumb_lx, gamepad.thumb_ly),
+        right_stick: (gamepad.thumb_rx, gamepad.thumb_ry),
+    }
+}
+
+fn apply_script_report(gamepad: &mut XGamepad, report: &Report) {
+    gamepad.buttons.raw = report
+        .buttons
+        .iter()
+        .fold(0, |raw, output| raw | xbutton(*output));
+    gamepad.left_trigger = report.left_trigger;
+    gamepad.right_trigger = report.right_trigger;
+    (gamepad.thumb_lx, gamepad.thumb_ly) = report.left_stick;
+    (gamepad.thumb_rx, gamepad.thumb_ry) = report.right_stick;
+}
+
+fn xbutton(output: common::GamepadOutput) -> u16 {
+    use common::GamepadOutput::*;
+    match output {
+        A => XButtons::A,
+        B => XButtons::B,
+        X => XButtons::X,
+        Y => XButtons::Y,
+        Start => XButtons::START,
+        Back => XButtons::BACK,
+        LeftShoulder => XButtons::LB,
+        RightShoulder => XButtons::RB,
+        LeftThumb => XButtons::LTHUMB,
+        RightThumb => XButtons::RTHUMB,
+        DPadUp => XButtons::UP,
+        DPadDown => XButtons::DOWN,
+        DPadLeft => XButtons::LEFT,
+        DPadRight => XButtons::RIGHT,
+        LeftTrigger | RightTrigger => 0,
     }
 }
@@ -0,0 +1,144 @@
+use common::FireMode;
+use std::time::Duration;
+
+/// Settings for one trigger, as driven by its mouse button.
+#[derive(Debug, Clone, Copy)]
+pub struct Trigger {
+    pub mode: FireMode,
+    /// Trigger pulls per minute in the auto and burst modes.
+    pub rpm: i32,
+    /// Percentage of each pulse for which the trigger is held down.
+    pub duty: i32,
+    /// Number of pulls making up a burst.
+    pub burst: i32,
+}
+
+impl Trigger {
+    fn period(&self) -> u128 {
+        60_000_000 / self.rpm.clamp(1, 60_000) as u128
+    }
+
+    /// Time between shots in the auto and burst modes.
+    pub fn shot_period(&self) -> Duration {
+        Duration::from_micros(self.period() as u64)
+    }
+
+    /// Trigger position once the button has been held for `held_for`.
+    /// Pulses are timed from the moment of the click.
+    pub fn value(&self, held_for: Duration) -> u8 {
+        let period = self.period();
+        let on = period * self.duty.clamp(0, 100) as u128 / 100;
+        let pulse = held_for.as_micros() % period < on;
+        let pulsing = match self.mode {
+            FireMode::Hold => return 255,
+            FireMode::Auto => true,
+            FireMode::Burst => held_for.as_micros() / period < self.burst.max(0) as u128,
+        };
+        if pulsing && pulse {
+            255
+        } else {
+            0
+        }
+    }
+
+    /// Whether the trigger position will still change while the button stays held.
+    pub fn animating(&self, held_for: Duration) -> bool {
+        match self.mode {
+            FireMode::Hold => false,
+            FireMode::Auto => true,
+            FireMode::Burst => held_for.as_micros() / self.period() < self.burst.max(0) as u128,
+        }
+    }
+}
+
+/// Whether the initial recoil impulse, lasting `impulse_millis`, is still running.
+pub fn in_recoil_impulse(held_for: Duration, impulse_millis: i32) -> bool {
+    held_for < Duration::from_millis(impulse_millis.max(0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{elapsed_since, mock::MockClock, Clock};
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn trigger(mode: FireMode, rpm: i32, duty: i32) -> Trigger {
+        Trigger {
+            mode,
+            rpm,
+            duty,
+            burst: 3,
+        }
+    }
+
+    #[test]
+    fn autofire_alternates_from_the_moment_of_the_click() {
+        let clock = MockClock::default();
+        clock.advance(ms(1234));
+        let pressed = clock.now().as_micros() as u64;
+        let auto = trigger(FireMode::Auto, 800, 50);
+
+        let mut pulses = vec![];
+        for _ in 0..8 {
+            pulses.push(auto.value(elapsed_since(pressed, clock.now())));
+            clock.advance(ms(20));
+        }
+        // 75 ms cycles: 0, 20 on; 40, 60 off; 80, 100 on; 120, 140 off
+        assert_eq!(pulses, vec![255, 255, 0, 0, 255, 255, 0, 0]);
+    }
+
+    #[test]
+    fn autofire_rate_and_duty_cycle_shape_the_pulses() {
+        // 300 RPM is a 200 ms cycle; 25% duty holds the trigger for 50 ms of it.
+        let auto = trigger(FireMode::Auto, 300, 25);
+        let pulses: Vec<u8> = [0, 49, 50, 199, 200, 249, 250]
+            .iter()
+            .map(|t| auto.value(ms(*t)))
+            .collect();
+        assert_eq!(pulses, vec![255, 255, 0, 0, 255, 255, 0]);
+    }
+
+    #[test]
+    fn degenerate_autofire_settings_do_not_panic() {
+        assert_eq!(trigger(FireMode::Auto, 0, 50).value(ms(10)), 255);
+        assert_eq!(trigger(FireMode::Auto, 800, 0).value(ms(10)), 0);
+        assert_eq!(trigger(FireMode::Auto, 800, 150).value(ms(10)), 255);
+        assert_eq!(trigger(FireMode::Auto, i32::MAX, 50).value(ms(10)), 255);
+    }
+
+    #[test]
+    fn held_trigger_stays_down() {
+        let hold = trigger(FireMode::Hold, 800, 50);
+        for held in [0, 37, 50, 1000] {
+            assert_eq!(hold.value(ms(held)), 255);
+            assert!(!hold.animating(ms(held)));
+        }
+    }
+
+    #[test]
+    fn burst_stops_after_its_pulses_while_still_held() {
+        // 600 RPM is a 100 ms cycle, so a burst of three is over after 300 ms.
+        let burst = trigger(FireMode::Burst, 600, 50);
+        let pulses: Vec<u8> = [0, 60, 100, 160, 200, 260, 300, 350, 400, 1000]
+            .iter()
+            .map(|t| burst.value(ms(*t)))
+            .collect();
+        assert_eq!(pulses, vec![255, 0, 255, 0, 255, 0, 0, 0, 0, 0]);
+        assert!(burst.animating(ms(299)));
+        assert!(!burst.animating(ms(300)));
+    }
+
+    #[test]
+    fn recoil_impulse_ends_after_its_duration() {
+        let clock = MockClock::default();
+        let pressed = clock.now().as_micros() as u64;
+        clock.advance(ms(49));
+        assert!(in_recoil_impulse(elapsed_since(pressed, clock.now()), 50));
+        clock.advance(ms(1));
+        assert!(!in_recoil_impulse(elapsed_since(pressed, clock.now()), 50));
+        assert!(!in_recoil_impulse(ms(0), -10));
+    }
+}
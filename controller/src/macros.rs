@@ -0,0 +1,219 @@
+use common::{GamepadOutput, MacroStep};
+use std::time::Duration;
+
+// Macro steps with holds broken down into their press, wait and release.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Press(GamepadOutput),
+    Release(GamepadOutput),
+    Wait(Duration),
+}
+
+fn expand(steps: &[MacroStep]) -> Vec<Action> {
+    let mut actions = vec![];
+    for step in steps {
+        match *step {
+            MacroStep::Press { output } => actions.push(Action::Press(output)),
+            MacroStep::Release { output } => actions.push(Action::Release(output)),
+            MacroStep::Hold { output, millis } => {
+                actions.push(Action::Press(output));
+                actions.push(Action::Wait(Duration::from_millis(millis as u64)));
+                actions.push(Action::Release(output));
+            }
+            MacroStep::Wait { millis } => {
+                actions.push(Action::Wait(Duration::from_millis(millis as u64)))
+            }
+        }
+    }
+    actions
+}
+
+#[derive(Debug)]
+struct Running {
+    actions: Vec<Action>,
+    next: usize,
+    // When the next action was due. Waits are measured from here rather than
+    // from the tick which noticed the previous one ending, so the lateness of
+    // individual ticks does not add up over a long macro.
+    due: Duration,
+}
+
+/// Plays back one macro, a step at a time, as the controller ticks.
+#[derive(Debug, Default)]
+pub struct MacroPlayer {
+    running: Option<Running>,
+    held: Vec<GamepadOutput>,
+}
+
+impl MacroPlayer {
+    /// Start the macro from the top, or cancel it if it is already running.
+    pub fn trigger(&mut self, steps: &[MacroStep], now: Duration) {
+        if self.running.is_some() {
+            self.cancel();
+        } else {
+            self.running = Some(Running {
+                actions: expand(steps),
+                next: 0,
+                due: now,
+            });
+        }
+    }
+
+    /// Stop the macro and release everything it is holding.
+    pub fn cancel(&mut self) {
+        self.running = None;
+        self.held.clear();
+    }
+
+    pub fn running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Outputs the macro currently holds down.
+    pub fn held(&self) -> &[GamepadOutput] {
+        &self.held
+    }
+
+    /// Carry out every step due by `now`. A press or release ends the update
+    /// so that each change reaches the gamepad in its own report; the steps
+    /// after it keep their timing all the same. Anything still held when the
+    /// macro ends is released.
+    pub fn update(&mut self, now: Duration) {
+        let Some(running) = self.running.as_mut() else {
+            return;
+        };
+        while let Some(action) = running.actions.get(running.next).copied() {
+            match action {
+                Action::Press(output) => {
+                    running.next += 1;
+                    if !self.held.contains(&output) {
+                        self.held.push(output);
+                        return;
+                    }
+                }
+                Action::Release(output) => {
+                    running.next += 1;
+                    if let Some(ix) = self.held.iter().position(|o| *o == output) {
+                        self.held.remove(ix);
+                        return;
+                    }
+                }
+                Action::Wait(duration) => {
+                    if now < running.due + duration {
+                        return;
+                    }
+                    running.due += duration;
+                    running.next += 1;
+                }
+            }
+        }
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{mock::MockClock, Clock};
+
+    const TICK: Duration = Duration::from_millis(2);
+
+    fn press(output: GamepadOutput) -> MacroStep {
+        MacroStep::Press { output }
+    }
+
+    fn wait(millis: u32) -> MacroStep {
+        MacroStep::Wait { millis }
+    }
+
+    // Tick the player until it finishes, noting each change in what it holds
+    // along with the time of the report that carried it.
+    fn play(player: &mut MacroPlayer, clock: &MockClock) -> Vec<(u128, Vec<GamepadOutput>)> {
+        let mut reports = vec![];
+        let mut last = vec![];
+        while player.running() {
+            player.update(clock.now());
+            if player.held() != last {
+                last = player.held().to_vec();
+                reports.push((clock.now().as_millis(), last.clone()));
+            }
+            clock.advance(TICK);
+        }
+        reports
+    }
+
+    #[test]
+    fn slide_cancel_is_timed_from_the_trigger() {
+        use GamepadOutput::*;
+        let clock = MockClock::default();
+        clock.advance(Duration::from_millis(1000));
+        let mut player = MacroPlayer::default();
+        player.trigger(
+            &[
+                MacroStep::Hold {
+                    output: B,
+                    millis: 50,
+                },
+                wait(50),
+                press(A),
+            ],
+            clock.now(),
+        );
+        assert_eq!(
+            play(&mut player, &clock),
+            vec![
+                (1000, vec![B]),
+                (1050, vec![]),
+                (1100, vec![A]),
+                // Released when the macro ends, one report later.
+                (1102, vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn late_ticks_do_not_accumulate_drift() {
+        let clock = MockClock::default();
+        let mut player = MacroPlayer::default();
+        let steps: Vec<MacroStep> = (0..10)
+            .flat_map(|_| {
+                [
+                    MacroStep::Hold {
+                        output: GamepadOutput::X,
+                        millis: 9,
+                    },
+                    wait(9),
+                ]
+            })
+            .collect();
+        player.trigger(&steps, clock.now());
+
+        // Every 9 ms wait is noticed up to a tick late, but presses still land
+        // on the 18 ms grid.
+        let presses: Vec<u128> = play(&mut player, &clock)
+            .into_iter()
+            .filter(|(_, held)| !held.is_empty())
+            .map(|(at, _)| at)
+            .collect();
+        assert_eq!(presses.len(), 10);
+        for (n, at) in presses.iter().enumerate() {
+            let expected = n as u128 * 18;
+            assert!((expected..expected + 2).contains(at), "{:?}", presses);
+        }
+    }
+
+    #[test]
+    fn triggering_again_cancels_and_releases() {
+        let clock = MockClock::default();
+        let mut player = MacroPlayer::default();
+        let steps = [press(GamepadOutput::RightTrigger), wait(500)];
+        player.trigger(&steps, clock.now());
+        player.update(clock.now());
+        assert_eq!(player.held(), &[GamepadOutput::RightTrigger]);
+
+        clock.advance(Duration::from_millis(100));
+        player.trigger(&steps, clock.now());
+        assert!(!player.running());
+        assert!(player.held().is_empty());
+    }
+}
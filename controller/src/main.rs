@@ -4,9 +4,23 @@ use log::{error, info};
 use std::ffi::c_void;
 use std::sync::atomic::Ordering;
 mod statics;
+use clock::{Clock, SystemClock};
 use statics::*;
 
+mod activation;
+mod bindings;
+mod clock;
 mod controller;
+mod firing;
+mod macros;
+mod metrics;
+mod movement;
+mod recoil;
+mod script;
+mod socd;
+mod stick;
+mod turbo;
+mod weapons;
 use vigem_client::*;
 use windows::{
     core::*,
@@ -23,32 +37,41 @@ unsafe extern "system" fn mouse_hook(code: i32, wparam: WPARAM, lparam: LPARAM)
     if !mouse_enabled && wparam.0 == WM_MOUSEMOVE as usize {
         return LRESULT { 0: 1 };
     } else if !mouse_enabled && wparam.0 == WM_LBUTTONDOWN as usize {
-        LBUTTONDOWN.store(true, Ordering::Relaxed);
-
-        // Always needed for left trigger, to compute initial recoil instant
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .expect("Cant get systemtime")
-            .as_millis() as u64;
+        // Always needed for left trigger, to compute initial recoil instant.
+        // Store the instant before the flag so the controller never pairs a
+        // fresh press with a stale instant.
+        let now = SystemClock.now().as_micros() as u64;
         LEFT_DOWN_INSTANT.store(now, Ordering::Relaxed);
+        LBUTTONDOWN.store(true, Ordering::Relaxed);
+        input_event();
 
         return LRESULT { 0: 1 };
     } else if !mouse_enabled && wparam.0 == WM_LBUTTONUP as usize {
         LBUTTONDOWN.store(false, Ordering::Relaxed);
+        input_event();
         return LRESULT { 0: 1 };
     } else if !mouse_enabled && wparam.0 == WM_RBUTTONDOWN as usize {
+        let now = SystemClock.now().as_micros() as u64;
+        RIGHT_DOWN_INSTANT.store(now, Ordering::Relaxed);
         RBUTTONDOWN.store(true, Ordering::Relaxed);
-        if RIGHT_AUTOFIRE.load(Ordering::Relaxed) {
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .expect("Cant get systemtime")
-                .as_millis() as u64;
-            RIGHT_DOWN_INSTANT.store(now, Ordering::Relaxed);
-        }
+        input_event();
         return LRESULT { 0: 1 };
     } else if !mouse_enabled && wparam.0 == WM_RBUTTONUP as usize {
         RBUTTONDOWN.store(false, Ordering::Relaxed);
+        input_event();
         return LRESULT { 0: 1 };
+    } else if !mouse_enabled
+        && wparam.0 == WM_MOUSEWHEEL as usize
+        && WEAPON_WHEEL.load(Ordering::Relaxed)
+    {
+        // The wheel delta is the high word of mouseData; negative is towards the user.
+        let info = lparam.0 as *const MSLLHOOKSTRUCT;
+        let forward = ((*info).mouseData >> 16) as i16 > 0;
+        let count = WEAPONS.lock().expect("weapons lock poisoned").len();
+        select_weapon(weapons::cycle_weapon(active_weapon_index(), count, forward));
+        input_event();
+        send_updated_buttonmap();
+        return CallNextHookEx(None, code, wparam, lparam);
     } else {
         return CallNextHookEx(None, code, wparam, lparam);
     }
@@ -59,59 +82,111 @@ unsafe extern "system" fn keyboard_hook(code: i32, wparam: WPARAM, lparam: LPARA
     let down = wparam.0 == WM_KEYDOWN as usize;
     let mouse_enabled = ENABLE_MOUSE.load(Ordering::Relaxed);
 
+    // The recoil modifier is passed on too, so it can double as a key the
+    // game itself uses.
+    if *pcode == CODE_RECOIL_MODIFIER.load(Ordering::Relaxed) {
+        RECOIL_MODIFIER.store(down, Ordering::Relaxed);
+    }
+
+    // The script sees every key, whatever else it is bound to.
+    if !mouse_enabled && SCRIPT_ENABLED.load(Ordering::Relaxed) {
+        SCRIPT_EVENTS
+            .lock()
+            .expect("script events lock poisoned")
+            .push((*pcode, down));
+        input_event();
+    }
+
+    if !mouse_enabled {
+        let triggered: Vec<usize> = MACROS
+            .lock()
+            .expect("macros lock poisoned")
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.trigger != 0 && m.trigger == *pcode)
+            .map(|(ix, _)| ix)
+            .collect();
+        if !triggered.is_empty() {
+            let mut held = MACRO_KEYS_HELD.lock().expect("macro keys lock poisoned");
+            let was_held = held.contains(&*pcode);
+            held.retain(|k| *k != *pcode);
+            if down {
+                held.push(*pcode);
+                if !was_held {
+                    MACRO_REQUESTS
+                        .lock()
+                        .expect("macro requests lock poisoned")
+                        .extend(triggered);
+                    input_event();
+                }
+            }
+            return LRESULT { 0: 1 };
+        }
+    }
+
+    // Weapon hotkeys are passed on to the game, which likely uses the same
+    // keys to switch weapons itself.
+    if !mouse_enabled && down {
+        let selected =
+            weapons::weapon_for_key(&WEAPONS.lock().expect("weapons lock poisoned"), *pcode);
+        if selected.is_some() && selected != active_weapon_index() {
+            select_weapon(selected);
+            info!("Selected weapon preset {:?}", selected);
+            input_event();
+            send_updated_buttonmap();
+        }
+    }
+
     // NB handle CAPS differently to these since it must be triggered in or out
     // of mouse mouse.
     if !mouse_enabled {
-        let pairs = vec![
-            (&CODE_BUTTON_START, &START),
-            (&CODE_DPAD_U, &DPADUP),
-            (&CODE_DPAD_D, &DPADDOWN),
-            (&CODE_DPAD_R, &DPADRIGHT),
-            (&CODE_DPAD_L, &DPADLEFT),
-            (&CODE_BUTTON_A, &BUTTONA),
-            (&CODE_BUTTON_B, &BUTTONB),
-            (&CODE_BUTTON_X, &BUTTONX),
-            (&CODE_BUTTON_Y, &BUTTONY),
-            (&CODE_SHOULDER_L, &SHOULDER_L),
-            (&CODE_SHOULDER_R, &SHOULDER_R),
-            (&CODE_LSTICK_D, &LSTICKDOWN),
-            (&CODE_LSTICK_U, &LSTICKUP),
-            (&CODE_LSTICK_R, &LSTICKRIGHT),
-            (&CODE_LSTICK_L, &LSTICKLEFT),
-        ];
-        for (code, button) in pairs {
-            if *pcode == code.load(Ordering::Relaxed) {
-                button.store(down, Ordering::Relaxed);
-                return LRESULT { 0: 1 };
+        let changes = KEY_RESOLVER
+            .lock()
+            .expect("key resolver lock poisoned")
+            .key(*pcode, down);
+        if let Some(changes) = changes {
+            let mut changed = false;
+            for (binding, down) in changes {
+                changed |= BINDINGS[binding].1.set(down);
+            }
+            if changed {
+                input_event();
             }
+            return LRESULT { 0: 1 };
         }
     }
 
     // Caps lock toggle mouse capture
-    if *pcode == 0x14 && down {
+    if *pcode == common::KEY_CAPTURE && down {
         info!("Toggled mouse capture.");
         let enabled = ENABLE_MOUSE.load(Ordering::Relaxed);
         ENABLE_MOUSE.store(!enabled, Ordering::Relaxed);
+        CANCEL_MACROS.store(true, Ordering::Relaxed);
+        wake_controller();
         return LRESULT { 0: 1 };
-    } else if *pcode == 0x70 && down {
+    } else if *pcode == common::KEY_SENSITIVITY_DOWN && down {
         // f1 decreases sensitivity
         let last = MOVEMENT_MULTIPLIER.fetch_sub(100, Ordering::Relaxed);
         info!("Decreased multiplier to {}", last - 100);
         send_updated_buttonmap();
-    } else if *pcode == 0x71 && down {
+    } else if *pcode == common::KEY_SENSITIVITY_UP && down {
         // f2 increases sensitivity
         let last = MOVEMENT_MULTIPLIER.fetch_add(100, Ordering::Relaxed);
         info!("Increased multiplier to {}", last + 100);
         send_updated_buttonmap();
-    } else if *pcode == 0x74 && down {
-        info!("Toggle left auto fire");
-        LEFT_AUTOFIRE.fetch_xor(true, Ordering::Relaxed);
+    } else if *pcode == common::KEY_LEFT_FIRE_MODE && down {
+        let mode = common::FireMode::from(LEFT_FIRE_MODE.load(Ordering::Relaxed)).next();
+        info!("Left fire mode {:?}", mode);
+        LEFT_FIRE_MODE.store(mode as u8, Ordering::Relaxed);
+        // Re-resolve the left button's settings, unless a preset overrides them.
+        select_weapon(active_weapon_index());
         send_updated_buttonmap();
-    } else if *pcode == 0x75 && down {
-        info!("Toggle right auto fire");
-        RIGHT_AUTOFIRE.fetch_xor(true, Ordering::Relaxed);
+    } else if *pcode == common::KEY_RIGHT_FIRE_MODE && down {
+        let mode = common::FireMode::from(RIGHT_FIRE_MODE.load(Ordering::Relaxed)).next();
+        info!("Right fire mode {:?}", mode);
+        RIGHT_FIRE_MODE.store(mode as u8, Ordering::Relaxed);
         send_updated_buttonmap();
-    } else if *pcode == 0x77 && down {
+    } else if *pcode == common::KEY_RECOIL && down {
         info!("Toggle recoil compensation");
         RECOIL_COMPENSATION_ACTIVE.fetch_xor(true, Ordering::Relaxed);
         send_updated_buttonmap();
@@ -125,11 +200,14 @@ fn send_updated_buttonmap() {
         if hwui.0 == 0 {
             exit_with_error(anyhow!("Could not find message sink for front end"));
         }
-        let mut data = create_button_map();
+        let mut data = match create_button_map().encode() {
+            Ok(data) => data,
+            Err(e) => return exit_with_error(e),
+        };
         let copydata = COPYDATASTRUCT {
             dwData: common::CopyTypes::ButtonMap as usize,
-            cbData: std::mem::size_of::<common::ButtonMapping>() as u32,
-            lpData: (&mut data) as *mut common::ButtonMapping as *mut std::ffi::c_void,
+            cbData: data.len() as u32,
+            lpData: data.as_mut_ptr() as *mut std::ffi::c_void,
         };
         let res = SendMessageA(
             hwui,
@@ -143,6 +221,71 @@ fn send_updated_buttonmap() {
     }
 }
 
+fn send_metrics() {
+    unsafe {
+        let hwui = FindWindowA(s!("serf-message-window"), s!("serf-frontend"));
+        if hwui.0 == 0 {
+            exit_with_error(anyhow!("Could not find message sink for front end"));
+        }
+        let mut data = metrics::snapshot();
+        let copydata = COPYDATASTRUCT {
+            dwData: common::CopyTypes::Metrics as usize,
+            cbData: std::mem::size_of::<common::Metrics>() as u32,
+            lpData: (&mut data) as *mut common::Metrics as *mut std::ffi::c_void,
+        };
+        let res = SendMessageA(
+            hwui,
+            WM_COPYDATA,
+            WPARAM(0),
+            LPARAM(&copydata as *const COPYDATASTRUCT as isize),
+        );
+        if res.0 != 1 {
+            exit_with_error(anyhow!("Failed dispatch message to sink for front end"));
+        }
+    }
+}
+
+// Posted to the message window when the script's error changes.
+const WM_SCRIPT_ERROR: u32 = WM_APP + 1;
+
+/// Have the main thread tell the front end about the error in
+/// `SCRIPT_ERROR`. Called from the controller thread, which must not block
+/// on the front end.
+pub fn script_error_changed() {
+    if let Some(window) = MESSAGE_WINDOW.get() {
+        unsafe {
+            PostMessageA(HWND(*window), WM_SCRIPT_ERROR, WPARAM(0), LPARAM(0));
+        }
+    }
+}
+
+/// Tell the front end why the script stopped, or that it is running again
+/// when `error` is empty.
+fn send_script_error(error: &str) {
+    unsafe {
+        let hwui = FindWindowA(s!("serf-message-window"), s!("serf-frontend"));
+        if hwui.0 == 0 {
+            // Not up yet; the script's error shows in the log instead.
+            error!("Script: {}", error);
+            return;
+        }
+        let copydata = COPYDATASTRUCT {
+            dwData: common::CopyTypes::ScriptError as usize,
+            cbData: error.len() as u32,
+            lpData: error.as_ptr() as *mut std::ffi::c_void,
+        };
+        let res = SendMessageA(
+            hwui,
+            WM_COPYDATA,
+            WPARAM(0),
+            LPARAM(&copydata as *const COPYDATASTRUCT as isize),
+        );
+        if res.0 != 1 {
+            error!("Failed to send the script's error to the front end");
+        }
+    }
+}
+
 fn exit_with_error(e: anyhow::Error) {
     unsafe {
         let message = format!("{:?}", e);
@@ -162,8 +305,18 @@ extern "system" fn wndproc(window: HWND, message: u32, wparam: WPARAM, lparam: L
         match message {
             WM_COPYDATA => {
                 let pdata: *const COPYDATASTRUCT = lparam.0 as *const u8 as *const COPYDATASTRUCT;
-                let pbmap = (*pdata).lpData as *mut common::ButtonMapping;
-                statics::apply_button_map(&*pbmap);
+                if (*pdata).dwData == common::CopyTypes::QueryMetrics as usize {
+                    send_metrics();
+                    return LRESULT(1);
+                }
+                let bytes = std::slice::from_raw_parts(
+                    (*pdata).lpData as *const u8,
+                    (*pdata).cbData as usize,
+                );
+                match common::ButtonMapping::decode(bytes) {
+                    Ok(map) => statics::apply_button_map(&map),
+                    Err(e) => exit_with_error(e),
+                }
                 return LRESULT(1);
             }
             WM_INPUT => {
@@ -189,8 +342,23 @@ extern "system" fn wndproc(window: HWND, message: u32, wparam: WPARAM, lparam: L
                     std::mem::size_of::<RAWINPUTHEADER>() as u32,
                 );
 
-                X.fetch_add(data.data.mouse.lLastX, Ordering::Relaxed);
-                Y.fetch_add(data.data.mouse.lLastY, Ordering::Relaxed);
+                // Only the first movement since the last report needs to wake the
+                // controller; it keeps ticking for as long as the mouse moves.
+                let x = X.fetch_add(data.data.mouse.lLastX, Ordering::Relaxed);
+                let y = Y.fetch_add(data.data.mouse.lLastY, Ordering::Relaxed);
+                if x == 0 && y == 0 {
+                    input_event();
+                }
+                LRESULT(0)
+            }
+            WM_SCRIPT_ERROR => {
+                let error = SCRIPT_ERROR
+                    .lock()
+                    .expect("script error lock poisoned")
+                    .take();
+                if let Some(error) = error {
+                    send_script_error(&error);
+                }
                 LRESULT(0)
             }
             WM_DESTROY => {
@@ -245,6 +413,8 @@ fn run_messages() -> Result<()> {
             None,
         );
 
+        _ = MESSAGE_WINDOW.set(hwnd.0);
+
         // register for raw mouse input
         let inputdevices = vec![RAWINPUTDEVICE {
             usUsage: HID_USAGE_GENERIC_MOUSE,
@@ -270,7 +440,9 @@ fn run_messages() -> Result<()> {
     let mut dir = std::env::current_exe().context("Couldn't get executable container directory")?;
     dir.pop();
     dir.push("serf-ui.exe");
+    // The front end reads the configuration, so `--config` is passed on to it.
     let mut child = std::process::Command::new(dir)
+        .args(std::env::args_os().skip(1))
         .spawn()
         .context("Failed to launch front end")?;
     std::thread::spawn(move || match child.wait() {
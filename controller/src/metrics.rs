@@ -0,0 +1,134 @@
+use common::{Metrics, LATENCY_BUCKETS};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Report rate is averaged over windows of this length.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+// Latest telemetry, updated by the controller thread and read by the message
+// window when the front end asks for it.
+pub static METRICS: Mutex<Recorder> = Mutex::new(Recorder::new());
+
+pub fn snapshot() -> Metrics {
+    METRICS.lock().expect("metrics lock poisoned").metrics
+}
+
+pub fn bucket_for(latency: Duration) -> usize {
+    let micros = latency.as_micros() as u64;
+    LATENCY_BUCKETS
+        .iter()
+        .position(|bound| micros <= *bound)
+        .unwrap_or(LATENCY_BUCKETS.len())
+}
+
+#[derive(Debug)]
+pub struct Recorder {
+    metrics: Metrics,
+    window_start: Option<Duration>,
+    window_reports: u64,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recorder {
+    pub const fn new() -> Self {
+        Recorder {
+            metrics: Metrics {
+                latency_histogram: [0; LATENCY_BUCKETS.len() + 1],
+                latency_max_micros: 0,
+                reports: 0,
+                report_rate: 0.,
+                overruns: 0,
+                motion_reports: 0,
+                saturated_reports: 0,
+                dropped_motion: 0,
+            },
+            window_start: None,
+            window_reports: 0,
+        }
+    }
+
+    /// Record a report sent at `now`, and the latency from the input event
+    /// that prompted it if there was one.
+    pub fn report(&mut self, now: Duration, latency: Option<Duration>) {
+        self.metrics.reports += 1;
+        if let Some(latency) = latency {
+            self.metrics.latency_histogram[bucket_for(latency)] += 1;
+            self.metrics.latency_max_micros = self
+                .metrics
+                .latency_max_micros
+                .max(latency.as_micros() as u64);
+        }
+
+        match self.window_start {
+            None => self.window_start = Some(now),
+            Some(start) => {
+                self.window_reports += 1;
+                let elapsed = now.saturating_sub(start);
+                if elapsed >= RATE_WINDOW {
+                    self.metrics.report_rate = self.window_reports as f64 / elapsed.as_secs_f64();
+                    self.window_start = Some(now);
+                    self.window_reports = 0;
+                }
+            }
+        }
+    }
+
+    pub fn overrun(&mut self) {
+        self.metrics.overruns += 1;
+    }
+
+    /// Record a report carrying mouse movement; `dropped` is the number of
+    /// mouse counts which were clipped off at the edge of the stick gate.
+    pub fn motion(&mut self, dropped: u64) {
+        self.metrics.motion_reports += 1;
+        if dropped > 0 {
+            self.metrics.saturated_reports += 1;
+            self.metrics.dropped_motion += dropped;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn us(micros: u64) -> Duration {
+        Duration::from_micros(micros)
+    }
+
+    #[test]
+    fn latencies_fall_into_the_expected_buckets() {
+        assert_eq!(bucket_for(us(0)), 0);
+        assert_eq!(bucket_for(us(250)), 0);
+        assert_eq!(bucket_for(us(251)), 1);
+        assert_eq!(bucket_for(us(16000)), LATENCY_BUCKETS.len() - 1);
+        assert_eq!(bucket_for(us(50000)), LATENCY_BUCKETS.len());
+    }
+
+    #[test]
+    fn report_rate_is_measured_per_window() {
+        let mut recorder = Recorder::new();
+        for tick in 0..=500 {
+            recorder.report(us(tick * 2000), None);
+        }
+        assert_eq!(recorder.metrics.reports, 501);
+        assert_eq!(recorder.metrics.report_rate, 500.);
+    }
+
+    #[test]
+    fn saturation_counts_only_motion_reports() {
+        let mut recorder = Recorder::new();
+        recorder.motion(0);
+        recorder.motion(12);
+        recorder.report(us(0), Some(us(900)));
+        assert_eq!(recorder.metrics.saturated_ratio(), 0.5);
+        assert_eq!(recorder.metrics.dropped_motion, 12);
+        assert_eq!(recorder.metrics.latency_histogram[2], 1);
+        assert_eq!(recorder.metrics.latency_max_micros, 900);
+    }
+}
@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+/// Turns the on/off state of the movement keys into a stick magnitude, so that
+/// keyboard movement can walk and ease in like an analog stick.
+#[derive(Debug, Default)]
+pub struct MovementShaper {
+    moving_since: Option<Duration>,
+    ramping: bool,
+}
+
+impl MovementShaper {
+    /// Fraction of full deflection to apply at `now`.
+    ///
+    /// `walk_speed` is a percentage applied while the walk modifier is held and
+    /// `ramp` the number of milliseconds taken to reach full speed after the
+    /// stick leaves the centre; zero disables the ramp.
+    pub fn magnitude(
+        &mut self,
+        moving: bool,
+        walking: bool,
+        walk_speed: i32,
+        ramp: i32,
+        now: Duration,
+    ) -> f64 {
+        self.ramping = false;
+        if !moving {
+            self.moving_since = None;
+            return 0.;
+        }
+        let since = *self.moving_since.get_or_insert(now);
+
+        let target = if walking {
+            walk_speed.clamp(0, 100) as f64 / 100.
+        } else {
+            1.
+        };
+        if ramp > 0 {
+            let ramp = Duration::from_millis(ramp as u64);
+            let progress = now.saturating_sub(since).as_secs_f64() / ramp.as_secs_f64();
+            self.ramping = progress < 1.;
+            target * progress.min(1.)
+        } else {
+            target
+        }
+    }
+
+    /// Whether the last magnitude was still climbing towards full speed.
+    pub fn ramping(&self) -> bool {
+        self.ramping
+    }
+}
+
+/// Scale a stick position by the given fraction of its deflection.
+pub fn scale((x, y): (i16, i16), fraction: f64) -> (i16, i16) {
+    (
+        (x as f64 * fraction).round() as i16,
+        (y as f64 * fraction).round() as i16,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn walking_scales_to_the_configured_speed() {
+        let mut shaper = MovementShaper::default();
+        assert_eq!(shaper.magnitude(true, false, 40, 0, ms(0)), 1.);
+        assert_eq!(shaper.magnitude(true, true, 40, 0, ms(1)), 0.4);
+        assert_eq!(shaper.magnitude(false, true, 40, 0, ms(2)), 0.);
+    }
+
+    #[test]
+    fn ramp_restarts_from_the_centre() {
+        let mut shaper = MovementShaper::default();
+        assert_eq!(shaper.magnitude(true, false, 50, 100, ms(1000)), 0.);
+        assert_eq!(shaper.magnitude(true, false, 50, 100, ms(1050)), 0.5);
+        assert!(shaper.ramping());
+        assert_eq!(shaper.magnitude(true, true, 50, 100, ms(1200)), 0.5);
+        assert!(!shaper.ramping());
+        assert_eq!(shaper.magnitude(false, false, 50, 100, ms(1210)), 0.);
+        assert_eq!(shaper.magnitude(true, false, 50, 100, ms(1220)), 0.);
+    }
+
+    #[test]
+    fn scale_keeps_direction() {
+        assert_eq!(scale((i16::MAX, -i16::MAX), 0.5), (16384, -16384));
+        assert_eq!(scale((0, 0), 0.5), (0, 0));
+    }
+}
@@ -0,0 +1,151 @@
+use common::{RecoilActivation, RecoilPoint};
+use std::time::Duration;
+
+/// A recoil compensation pattern, ready to be sampled at each tick.
+#[derive(Debug, Default, Clone)]
+pub struct RecoilPattern {
+    // (milliseconds since the trigger was pulled, dx, dy), in time order
+    points: Vec<(f64, f64, f64)>,
+}
+
+impl RecoilPattern {
+    /// Per-shot patterns are laid out one point per `shot_period`, so that
+    /// they stay in step with autofire.
+    pub fn new(points: &[RecoilPoint], per_shot: bool, shot_period: Duration) -> Self {
+        let period = shot_period.as_secs_f64() * 1000.;
+        let mut points: Vec<(f64, f64, f64)> = points
+            .iter()
+            .enumerate()
+            .map(|(shot, p)| {
+                let time = if per_shot {
+                    shot as f64 * period
+                } else {
+                    p.time as f64
+                };
+                (time, p.dx as f64, p.dy as f64)
+            })
+            .collect();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        RecoilPattern { points }
+    }
+
+    /// Offset, in percent of stick deflection, once the trigger has been held
+    /// for `held_for`. Linearly interpolated between points; before the first
+    /// point and after the last the nearest point applies.
+    pub fn offset(&self, held_for: Duration) -> (f64, f64) {
+        let t = held_for.as_secs_f64() * 1000.;
+        let next = self.points.iter().position(|p| p.0 > t);
+        match next {
+            None => self.points.last().map_or((0., 0.), |p| (p.1, p.2)),
+            Some(0) => (self.points[0].1, self.points[0].2),
+            Some(ix) => {
+                let (t0, x0, y0) = self.points[ix - 1];
+                let (t1, x1, y1) = self.points[ix];
+                let f = (t - t0) / (t1 - t0);
+                (x0 + (x1 - x0) * f, y0 + (y1 - y0) * f)
+            }
+        }
+    }
+
+    /// Whether the offset will still change with time.
+    pub fn running(&self, held_for: Duration) -> bool {
+        self.points
+            .last()
+            .is_some_and(|p| held_for.as_secs_f64() * 1000. < p.0)
+    }
+}
+
+/// Whether compensation applies to a shot fired right now.
+pub fn compensation_active(activation: RecoilActivation, aiming: bool, modifier: bool) -> bool {
+    match activation {
+        RecoilActivation::WhileAiming => aiming,
+        RecoilActivation::Always => true,
+        RecoilActivation::WithModifier => modifier,
+    }
+}
+
+/// Adds a compensation offset, in percent of full stick deflection, to the raw
+/// right stick position. This must happen before the stick is gated: added to
+/// an already clamped position it would be lost whenever the mouse saturates
+/// the stick.
+pub fn compensate((x, y): (i32, i32), (dx, dy): (f64, f64)) -> (i32, i32) {
+    let percent = i16::MAX as f64 / 100.;
+    (
+        x.saturating_add((dx * percent) as i32),
+        y.saturating_add((dy * percent) as i32),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stick::apply_gate;
+    use common::StickGate;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn point(time: i32, dx: i32, dy: i32) -> RecoilPoint {
+        RecoilPoint { time, dx, dy }
+    }
+
+    #[test]
+    fn empty_pattern_has_no_offset() {
+        let pattern = RecoilPattern::new(&[], false, ms(75));
+        assert_eq!(pattern.offset(ms(100)), (0., 0.));
+        assert!(!pattern.running(ms(0)));
+    }
+
+    #[test]
+    fn timed_points_are_interpolated() {
+        let pattern = RecoilPattern::new(
+            &[point(200, 10, -20), point(0, 0, -10), point(400, -10, -20)],
+            false,
+            ms(75),
+        );
+        assert_eq!(pattern.offset(ms(0)), (0., -10.));
+        assert_eq!(pattern.offset(ms(100)), (5., -15.));
+        assert_eq!(pattern.offset(ms(300)), (0., -20.));
+        assert_eq!(pattern.offset(ms(1000)), (-10., -20.));
+        assert!(pattern.running(ms(399)));
+        assert!(!pattern.running(ms(400)));
+    }
+
+    #[test]
+    fn per_shot_points_follow_the_fire_rate() {
+        // The stored times are ignored; shots land every 100 ms.
+        let pattern = RecoilPattern::new(
+            &[point(999, 0, -10), point(5, 4, -10), point(0, 8, -6)],
+            true,
+            ms(100),
+        );
+        assert_eq!(pattern.offset(ms(50)), (2., -10.));
+        assert_eq!(pattern.offset(ms(100)), (4., -10.));
+        assert_eq!(pattern.offset(ms(150)), (6., -8.));
+        assert!(!pattern.running(ms(200)));
+    }
+
+    #[test]
+    fn activation_follows_the_configured_condition() {
+        use RecoilActivation::*;
+        assert!(compensation_active(WhileAiming, true, false));
+        assert!(!compensation_active(WhileAiming, false, true));
+        assert!(compensation_active(Always, false, false));
+        assert!(compensation_active(WithModifier, false, true));
+        assert!(!compensation_active(WithModifier, true, false));
+    }
+
+    #[test]
+    fn compensation_is_gated_along_with_the_mouse_motion() {
+        // Small movements keep the full 20% pull-down.
+        let (x, y) = compensate((1000, 0), (0., -20.));
+        assert_eq!(apply_gate(StickGate::Circle, x, y), (1000, -6553));
+        // A hard flick right is still pulled down, but the stick stays inside
+        // its gate rather than being pushed past the rim after clamping.
+        let (x, y) = compensate((100_000, 0), (0., -20.));
+        let (x, y) = apply_gate(StickGate::Circle, x, y);
+        assert!(y < 0);
+        assert!((x as f64).hypot(y as f64) <= i16::MAX as f64 + 1.);
+    }
+}
@@ -0,0 +1,429 @@
+use common::{BindingTarget, GamepadOutput};
+use log::info;
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What a script can see of the user's input.
+#[derive(Debug, Clone, Default)]
+pub struct Input {
+    /// Whether each key binding is down, in `BindingTarget` order.
+    pub bindings: [bool; 19],
+    /// Mouse motion since the last report, in counts.
+    pub mouse: (i32, i32),
+    pub firing: bool,
+    pub aiming: bool,
+    pub now: Duration,
+    /// Keyboard keys currently held, by virtual key code. `Script::run` keeps
+    /// this up to date from the key events it is given.
+    pub held: Vec<i32>,
+}
+
+/// The outgoing gamepad report, as a script may change it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Buttons down, not including the triggers.
+    pub buttons: Vec<GamepadOutput>,
+    pub left_trigger: u8,
+    pub right_trigger: u8,
+    pub left_stick: (i16, i16),
+    pub right_stick: (i16, i16),
+}
+
+impl Report {
+    fn press(&mut self, output: GamepadOutput) {
+        match output {
+            GamepadOutput::LeftTrigger => self.left_trigger = u8::MAX,
+            GamepadOutput::RightTrigger => self.right_trigger = u8::MAX,
+            button if !self.buttons.contains(&button) => self.buttons.push(button),
+            _ => {}
+        }
+    }
+
+    fn release(&mut self, output: GamepadOutput) {
+        match output {
+            GamepadOutput::LeftTrigger => self.left_trigger = 0,
+            GamepadOutput::RightTrigger => self.right_trigger = 0,
+            button => self.buttons.retain(|b| *b != button),
+        }
+    }
+
+    fn pressed(&self, output: GamepadOutput) -> bool {
+        match output {
+            GamepadOutput::LeftTrigger => self.left_trigger > 0,
+            GamepadOutput::RightTrigger => self.right_trigger > 0,
+            button => self.buttons.contains(&button),
+        }
+    }
+}
+
+fn output(name: &str) -> Result<GamepadOutput, Box<EvalAltResult>> {
+    GamepadOutput::ALL
+        .into_iter()
+        .find(|output| output.label().eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("Unknown gamepad output '{}'", name).into())
+}
+
+fn binding(name: &str) -> Result<BindingTarget, Box<EvalAltResult>> {
+    BindingTarget::ALL
+        .into_iter()
+        .find(|target| target.label().eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("Unknown binding '{}'", name).into())
+}
+
+fn stick(value: i64) -> i16 {
+    value.clamp(i16::MIN as i64, i16::MAX as i64) as i16
+}
+
+fn trigger(value: i64) -> u8 {
+    value.clamp(0, u8::MAX as i64) as u8
+}
+
+// `read_time` is set whenever a script reads `input.time`.
+fn engine(read_time: Arc<AtomicBool>) -> Engine {
+    let mut engine = Engine::new();
+    // Scripts come with shared profiles, so they may not load files.
+    engine.set_module_resolver(rhai::module_resolvers::DummyModuleResolver::new());
+    // Scripts run on the controller thread, so keep a runaway one from
+    // stalling the gamepad.
+    engine.set_max_operations(100_000);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(4096);
+    engine.set_max_array_size(1024);
+    engine.set_max_map_size(1024);
+    engine.on_print(|text| info!("Script: {}", text));
+
+    engine
+        .register_type_with_name::<Input>("Input")
+        .register_fn("held", |input: &mut Input, name: &str| {
+            binding(name).map(|target| input.bindings[target.index()])
+        })
+        .register_fn("key", |input: &mut Input, code: i64| {
+            input.held.iter().any(|key| *key as i64 == code)
+        })
+        .register_get("mouse_x", |input: &mut Input| input.mouse.0 as i64)
+        .register_get("mouse_y", |input: &mut Input| input.mouse.1 as i64)
+        .register_get("firing", |input: &mut Input| input.firing)
+        .register_get("aiming", |input: &mut Input| input.aiming)
+        .register_get("time", move |input: &mut Input| {
+            read_time.store(true, Ordering::Relaxed);
+            input.now.as_millis() as i64
+        });
+
+    engine
+        .register_type_with_name::<Report>("Report")
+        .register_fn("press", |report: &mut Report, name: &str| {
+            output(name).map(|output| report.press(output))
+        })
+        .register_fn("release", |report: &mut Report, name: &str| {
+            output(name).map(|output| report.release(output))
+        })
+        .register_fn("pressed", |report: &mut Report, name: &str| {
+            output(name).map(|output| report.pressed(output))
+        })
+        .register_get_set(
+            "left_x",
+            |report: &mut Report| report.left_stick.0 as i64,
+            |report: &mut Report, value: i64| report.left_stick.0 = stick(value),
+        )
+        .register_get_set(
+            "left_y",
+            |report: &mut Report| report.left_stick.1 as i64,
+            |report: &mut Report, value: i64| report.left_stick.1 = stick(value),
+        )
+        .register_get_set(
+            "right_x",
+            |report: &mut Report| report.right_stick.0 as i64,
+            |report: &mut Report, value: i64| report.right_stick.0 = stick(value),
+        )
+        .register_get_set(
+            "right_y",
+            |report: &mut Report| report.right_stick.1 as i64,
+            |report: &mut Report, value: i64| report.right_stick.1 = stick(value),
+        )
+        .register_get_set(
+            "left_trigger",
+            |report: &mut Report| report.left_trigger as i64,
+            |report: &mut Report, value: i64| report.left_trigger = trigger(value),
+        )
+        .register_get_set(
+            "right_trigger",
+            |report: &mut Report| report.right_trigger as i64,
+            |report: &mut Report, value: i64| report.right_trigger = trigger(value),
+        );
+    engine
+}
+
+/// A game's mapping script.
+///
+/// The script runs once for each key event, with `key` set to a map of its
+/// `code` and whether it went `down`, and then once for the report itself
+/// with `key` set to `()`. Each run sees the user's input as `input`, the
+/// report as built so far as `report`, which it may change, and `state`, a
+/// map kept from one run to the next.
+///
+/// Scripts run only when there is input, unless they ask for more: a script
+/// which reads `input.time`, or sets `state.tick` to `true`, is run at the
+/// sampling rate until a run does neither.
+///
+/// A script which fails to compile or raises an error is stopped, leaving
+/// the report as it was, until its source changes.
+pub struct Script {
+    engine: Engine,
+    source: String,
+    ast: Option<AST>,
+    state: Map,
+    held: Vec<i32>,
+    error: Option<String>,
+    read_time: Arc<AtomicBool>,
+    ticking: bool,
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Script {
+    pub fn new() -> Self {
+        let read_time = Arc::new(AtomicBool::new(false));
+        Script {
+            engine: engine(read_time.clone()),
+            source: String::new(),
+            ast: None,
+            state: Map::new(),
+            held: vec![],
+            error: None,
+            read_time,
+            ticking: false,
+        }
+    }
+
+    /// Compile `source` if it differs from the script already loaded.
+    pub fn load(&mut self, source: &str) {
+        if source == self.source {
+            return;
+        }
+        self.source = source.to_string();
+        self.state.clear();
+        self.held.clear();
+        self.ast = None;
+        self.error = None;
+        self.ticking = false;
+        if source.trim().is_empty() {
+            return;
+        }
+        match self.engine.compile(source) {
+            Ok(ast) => self.ast = Some(ast),
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+
+    pub fn running(&self) -> bool {
+        self.ast.is_some()
+    }
+
+    /// Whether the script asked, in its last run, to be run again with time
+    /// alone.
+    pub fn ticking(&self) -> bool {
+        self.ticking
+    }
+
+    /// Why the script stopped, if it did.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Run the script for each of `events`, as (key code, down) pairs, and
+    /// then for the report.
+    pub fn run(&mut self, input: &Input, report: &mut Report, events: &[(i32, bool)]) {
+        if self.ast.is_none() {
+            return;
+        }
+        let mut input = Input {
+            held: std::mem::take(&mut self.held),
+            ..input.clone()
+        };
+        let mut scratch = report.clone();
+        self.read_time.store(false, Ordering::Relaxed);
+        let mut result = Ok(());
+        for &(code, down) in events {
+            // Key repeat is not an event.
+            if down == input.held.contains(&code) {
+                continue;
+            }
+            if down {
+                input.held.push(code);
+            } else {
+                input.held.retain(|key| *key != code);
+            }
+            let mut key = Map::new();
+            key.insert("code".into(), (code as i64).into());
+            key.insert("down".into(), down.into());
+            result = self.eval(&input, &mut scratch, key.into());
+            if result.is_err() {
+                break;
+            }
+        }
+        if result.is_ok() {
+            result = self.eval(&input, &mut scratch, Dynamic::UNIT);
+        }
+        self.held = input.held;
+        self.ticking = result.is_ok()
+            && (self.read_time.load(Ordering::Relaxed)
+                || self.state.get("tick").and_then(|tick| tick.as_bool().ok()) == Some(true));
+        match result {
+            Ok(()) => *report = scratch,
+            Err(e) => {
+                self.ast = None;
+                self.error = Some(e);
+            }
+        }
+    }
+
+    fn eval(&mut self, input: &Input, report: &mut Report, key: Dynamic) -> Result<(), String> {
+        let ast = self.ast.as_ref().expect("only evaluated while running");
+        let mut scope = Scope::new();
+        scope.push("input", input.clone());
+        scope.push("report", report.clone());
+        scope.push("state", std::mem::take(&mut self.state));
+        scope.push_constant("key", key);
+        let result = self.engine.run_ast_with_scope(&mut scope, ast);
+        self.state = scope.get_value("state").unwrap_or_default();
+        result.map_err(|e| e.to_string())?;
+        *report = scope
+            .get_value("report")
+            .ok_or("The script replaced `report` with something else")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHIFT: i32 = 0xa0;
+
+    fn compiled(source: &str) -> Script {
+        let mut script = Script::new();
+        script.load(source);
+        assert_eq!(script.error(), None);
+        script
+    }
+
+    #[test]
+    fn sprints_while_walking_forward_unless_aiming() {
+        let mut script = compiled(
+            r#"
+            if input.held("Stick Up") && !input.aiming {
+                report.press("Left thumb");
+            }
+            "#,
+        );
+        let mut input = Input::default();
+        input.bindings[BindingTarget::StickUp.index()] = true;
+        let mut report = Report::default();
+        script.run(&input, &mut report, &[]);
+        assert_eq!(report.buttons, vec![GamepadOutput::LeftThumb]);
+
+        input.aiming = true;
+        let mut report = Report::default();
+        script.run(&input, &mut report, &[]);
+        assert!(report.buttons.is_empty());
+    }
+
+    #[test]
+    fn key_events_and_state_carry_between_runs() {
+        // Count shift presses, ignoring key repeat, and hold A on odd counts.
+        let mut script = compiled(
+            r#"
+            if key != () && key.code == 0xa0 && key.down {
+                state.presses = (state.presses ?? 0) + 1;
+            }
+            if key == () && (state.presses ?? 0) % 2 == 1 && input.key(0xa0) {
+                report.press("A");
+                report.right_x = 100000;
+            }
+            "#,
+        );
+        let input = Input::default();
+        let mut report = Report::default();
+        script.run(&input, &mut report, &[(SHIFT, true), (SHIFT, true)]);
+        assert_eq!(report.buttons, vec![GamepadOutput::A]);
+        assert_eq!(report.right_stick.0, i16::MAX);
+
+        let mut report = Report::default();
+        script.run(&input, &mut report, &[(SHIFT, false), (SHIFT, true)]);
+        assert!(report.buttons.is_empty());
+    }
+
+    #[test]
+    fn errors_stop_the_script_without_touching_the_report() {
+        let mut broken = Script::new();
+        broken.load("report.press(");
+        assert!(!broken.running());
+        assert!(broken.error().is_some());
+
+        let mut script = compiled(r#"report.press("B"); report.press("Turbo");"#);
+        let mut report = Report::default();
+        script.run(&Input::default(), &mut report, &[]);
+        assert_eq!(report, Report::default());
+        assert!(!script.running());
+        assert!(script.error().unwrap().contains("Turbo"));
+
+        // Runaway scripts are cut off rather than stalling the controller.
+        let mut script = compiled("loop {}");
+        script.run(&Input::default(), &mut report, &[]);
+        assert!(!script.running());
+
+        // Editing the source starts it again.
+        script.load(r#"report.press("B");"#);
+        script.run(&Input::default(), &mut report, &[]);
+        assert_eq!(report.buttons, vec![GamepadOutput::B]);
+    }
+
+    #[test]
+    fn scripts_cannot_import_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("helper.rhai"), "fn double(n) { n * 2 }").unwrap();
+        let mut script = compiled(&format!(
+            "import {:?} as helper; report.right_x = helper::double(2);",
+            dir.path().join("helper")
+        ));
+        let mut report = Report::default();
+        script.run(&Input::default(), &mut report, &[]);
+        assert_eq!(report, Report::default());
+        assert!(script.error().is_some());
+    }
+
+    #[test]
+    fn scripts_tick_only_while_they_ask_to() {
+        let mut report = Report::default();
+        let mut script = compiled(r#"if input.held("A") { report.press("B"); }"#);
+        script.run(&Input::default(), &mut report, &[]);
+        assert!(!script.ticking());
+
+        let mut script = compiled(r#"if input.firing { report.right_x = input.time; }"#);
+        script.run(&Input::default(), &mut report, &[]);
+        assert!(!script.ticking());
+        let firing = Input {
+            firing: true,
+            ..Default::default()
+        };
+        script.run(&firing, &mut report, &[]);
+        assert!(script.ticking());
+
+        let mut script = compiled("state.tick = input.aiming;");
+        let aiming = Input {
+            aiming: true,
+            ..Default::default()
+        };
+        script.run(&aiming, &mut report, &[]);
+        assert!(script.ticking());
+        script.run(&Input::default(), &mut report, &[]);
+        assert!(!script.ticking());
+    }
+}
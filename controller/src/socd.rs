@@ -0,0 +1,129 @@
+use common::SocdMode;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Direction {
+    Negative,
+    Positive,
+}
+
+/// Resolves the two opposing keys of a single stick axis into a direction.
+///
+/// Press order is tracked from one tick to the next, so two keys which go down
+/// within the same tick count as simultaneous and resolve to neutral under the
+/// order based modes.
+#[derive(Debug, Default)]
+pub struct SocdAxis {
+    negative: bool,
+    positive: bool,
+    latest: Option<Direction>,
+}
+
+impl SocdAxis {
+    /// Returns -1, 0 or 1 for the resolved direction of the axis.
+    pub fn resolve(&mut self, mode: SocdMode, negative: bool, positive: bool) -> i32 {
+        let negative_pressed = negative && !self.negative;
+        let positive_pressed = positive && !self.positive;
+        if negative_pressed && positive_pressed {
+            self.latest = None;
+        } else if negative_pressed {
+            self.latest = Some(Direction::Negative);
+        } else if positive_pressed {
+            self.latest = Some(Direction::Positive);
+        }
+        self.negative = negative;
+        self.positive = positive;
+
+        match (negative, positive) {
+            (false, false) => 0,
+            (true, false) => -1,
+            (false, true) => 1,
+            (true, true) => match (mode, self.latest) {
+                (SocdMode::Neutral, _) => 0,
+                (SocdMode::PositiveWins, _) => 1,
+                (SocdMode::NegativeWins, _) => -1,
+                (_, None) => 0,
+                (SocdMode::LastInputWins, Some(Direction::Negative)) => -1,
+                (SocdMode::LastInputWins, Some(Direction::Positive)) => 1,
+                (SocdMode::FirstInputWins, Some(Direction::Negative)) => 1,
+                (SocdMode::FirstInputWins, Some(Direction::Positive)) => -1,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feed a sequence of (negative, positive) key states and collect the output.
+    fn run(mode: SocdMode, states: &[(bool, bool)]) -> Vec<i32> {
+        let mut axis = SocdAxis::default();
+        states
+            .iter()
+            .map(|(n, p)| axis.resolve(mode, *n, *p))
+            .collect()
+    }
+
+    const TAP_WHILE_HOLDING: [(bool, bool); 4] =
+        [(false, true), (true, true), (false, true), (false, false)];
+
+    #[test]
+    fn single_direction_is_unaffected_by_mode() {
+        for mode in SocdMode::ALL {
+            assert_eq!(
+                run(mode, &[(true, false), (false, false), (false, true)]),
+                vec![-1, 0, 1]
+            );
+        }
+    }
+
+    #[test]
+    fn last_input_wins_lets_a_tap_override_the_held_key() {
+        assert_eq!(
+            run(SocdMode::LastInputWins, &TAP_WHILE_HOLDING),
+            vec![1, -1, 1, 0]
+        );
+    }
+
+    #[test]
+    fn last_input_wins_follows_a_repress() {
+        assert_eq!(
+            run(
+                SocdMode::LastInputWins,
+                &[(true, false), (true, true), (false, true), (true, true)]
+            ),
+            vec![-1, 1, 1, -1]
+        );
+    }
+
+    #[test]
+    fn first_input_wins_keeps_the_held_key() {
+        assert_eq!(
+            run(SocdMode::FirstInputWins, &TAP_WHILE_HOLDING),
+            vec![1, 1, 1, 0]
+        );
+    }
+
+    #[test]
+    fn neutral_cancels_opposing_keys() {
+        assert_eq!(run(SocdMode::Neutral, &TAP_WHILE_HOLDING), vec![1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn fixed_priority_ignores_press_order() {
+        assert_eq!(
+            run(SocdMode::PositiveWins, &TAP_WHILE_HOLDING),
+            vec![1, 1, 1, 0]
+        );
+        assert_eq!(
+            run(SocdMode::NegativeWins, &TAP_WHILE_HOLDING),
+            vec![1, -1, 1, 0]
+        );
+    }
+
+    #[test]
+    fn simultaneous_presses_are_neutral_for_order_based_modes() {
+        assert_eq!(run(SocdMode::LastInputWins, &[(true, true)]), vec![0]);
+        assert_eq!(run(SocdMode::FirstInputWins, &[(true, true)]), vec![0]);
+    }
+}
@@ -1,8 +1,87 @@
-use std::sync::atomic::{AtomicBool, AtomicI16, AtomicI32, AtomicU64, Ordering};
+use std::sync::atomic::{
+    AtomicBool, AtomicI16, AtomicI32, AtomicU32, AtomicU64, AtomicU8, Ordering,
+};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::Thread;
+
+use crate::bindings::KeyResolver;
+use crate::clock::{Clock, SystemClock};
+use crate::firing::Trigger;
+use crate::recoil::RecoilPattern;
+use common::WeaponPreset;
+
+/// A binding's key as seen by the keyboard hook: whether it is down, and how
+/// many times it has been pressed, so that a press shorter than a controller
+/// tick is not lost.
+#[derive(Debug)]
+pub struct KeyState {
+    down: AtomicBool,
+    presses: AtomicU32,
+}
+
+impl Default for KeyState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyState {
+    pub const fn new() -> Self {
+        KeyState {
+            down: AtomicBool::new(false),
+            presses: AtomicU32::new(0),
+        }
+    }
+
+    /// Returns true if this changed the state.
+    pub fn set(&self, down: bool) -> bool {
+        let changed = self.down.swap(down, Ordering::Relaxed) != down;
+        if changed && down {
+            self.presses.fetch_add(1, Ordering::Relaxed);
+        }
+        changed
+    }
+
+    pub fn is_down(&self) -> bool {
+        self.down.load(Ordering::Relaxed)
+    }
+
+    pub fn presses(&self) -> u32 {
+        self.presses.load(Ordering::Relaxed)
+    }
+}
+
+// The controller thread, parked whenever it has nothing time dependent to do.
+pub static CONTROLLER_THREAD: OnceLock<Thread> = OnceLock::new();
+// The handle of the window whose messages the main thread processes.
+pub static MESSAGE_WINDOW: OnceLock<isize> = OnceLock::new();
 
 pub static MOVEMENT_MULTIPLIER: AtomicI16 = AtomicI16::new(2000);
 pub static INTERVAL_MICROS: AtomicU64 = AtomicU64::new(2000);
+pub static STICK_GATE: AtomicU8 = AtomicU8::new(common::StickGate::Square as u8);
+pub static SOCD_HORIZONTAL: AtomicU8 = AtomicU8::new(common::SocdMode::LastInputWins as u8);
+pub static SOCD_VERTICAL: AtomicU8 = AtomicU8::new(common::SocdMode::LastInputWins as u8);
+pub static WALK_SPEED: AtomicI32 = AtomicI32::new(50);
+pub static MOVEMENT_RAMP: AtomicI32 = AtomicI32::new(0);
+
+// Weapon presets; while one is active it overrides the left mouse button's
+// firing and recoil settings. ACTIVE_WEAPON is -1 when none is.
+pub static WEAPONS: Mutex<Vec<WeaponPreset>> = Mutex::new(Vec::new());
+pub static ACTIVE_WEAPON: AtomicI32 = AtomicI32::new(-1);
+pub static WEAPON_WHEEL: AtomicBool = AtomicBool::new(false);
+// The active preset's settings, resolved by `select_weapon` so the controller
+// need not look them up and sort the recoil pattern on every tick.
+static ARMAMENT: Mutex<Option<Arc<Armament>>> = Mutex::new(None);
+
+// Configured macros, and the indices of those whose trigger key has been
+// pressed since the controller last looked.
+pub static MACROS: Mutex<Vec<common::Macro>> = Mutex::new(Vec::new());
+pub static MACRO_REQUESTS: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+// Macro trigger keys currently held, so that key repeat does not retrigger.
+pub static MACRO_KEYS_HELD: Mutex<Vec<i32>> = Mutex::new(Vec::new());
+pub static CANCEL_MACROS: AtomicBool = AtomicBool::new(false);
 
+// Microseconds on the monotonic controller clock at which each mouse button went down
 pub static LEFT_DOWN_INSTANT: AtomicU64 = AtomicU64::new(0);
 pub static RIGHT_DOWN_INSTANT: AtomicU64 = AtomicU64::new(0);
 
@@ -10,24 +89,58 @@ pub static RIGHT_DOWN_INSTANT: AtomicU64 = AtomicU64::new(0);
 pub static ENABLE_MOUSE: AtomicBool = AtomicBool::new(true);
 pub static LBUTTONDOWN: AtomicBool = AtomicBool::new(false);
 pub static RBUTTONDOWN: AtomicBool = AtomicBool::new(false);
-pub static START: AtomicBool = AtomicBool::new(false);
-pub static DPADUP: AtomicBool = AtomicBool::new(false);
-pub static DPADDOWN: AtomicBool = AtomicBool::new(false);
-pub static DPADRIGHT: AtomicBool = AtomicBool::new(false);
-pub static DPADLEFT: AtomicBool = AtomicBool::new(false);
-pub static LSTICKUP: AtomicBool = AtomicBool::new(false);
-pub static LSTICKDOWN: AtomicBool = AtomicBool::new(false);
-pub static LSTICKRIGHT: AtomicBool = AtomicBool::new(false);
-pub static LSTICKLEFT: AtomicBool = AtomicBool::new(false);
-pub static BUTTONA: AtomicBool = AtomicBool::new(false);
-pub static BUTTONB: AtomicBool = AtomicBool::new(false);
-pub static BUTTONX: AtomicBool = AtomicBool::new(false);
-pub static BUTTONY: AtomicBool = AtomicBool::new(false);
-pub static SHOULDER_L: AtomicBool = AtomicBool::new(false);
-pub static SHOULDER_R: AtomicBool = AtomicBool::new(false);
-pub static THUMB_L: AtomicBool = AtomicBool::new(false);
-pub static THUMB_R: AtomicBool = AtomicBool::new(false);
-pub static BACK: AtomicBool = AtomicBool::new(false);
+pub static START: KeyState = KeyState::new();
+pub static DPADUP: KeyState = KeyState::new();
+pub static DPADDOWN: KeyState = KeyState::new();
+pub static DPADRIGHT: KeyState = KeyState::new();
+pub static DPADLEFT: KeyState = KeyState::new();
+pub static LSTICKUP: KeyState = KeyState::new();
+pub static LSTICKDOWN: KeyState = KeyState::new();
+pub static LSTICKRIGHT: KeyState = KeyState::new();
+pub static LSTICKLEFT: KeyState = KeyState::new();
+pub static BUTTONA: KeyState = KeyState::new();
+pub static BUTTONB: KeyState = KeyState::new();
+pub static BUTTONX: KeyState = KeyState::new();
+pub static BUTTONY: KeyState = KeyState::new();
+pub static SHOULDER_L: KeyState = KeyState::new();
+pub static SHOULDER_R: KeyState = KeyState::new();
+pub static THUMB_L: KeyState = KeyState::new();
+pub static THUMB_R: KeyState = KeyState::new();
+pub static BACK: KeyState = KeyState::new();
+pub static WALK: KeyState = KeyState::new();
+
+// The key bindings, numbered as in `common::KeyBindings::codes`.
+pub static BINDINGS: [(&AtomicI32, &KeyState); 19] = [
+    (&CODE_BUTTON_START, &START),
+    (&CODE_DPAD_U, &DPADUP),
+    (&CODE_DPAD_D, &DPADDOWN),
+    (&CODE_DPAD_R, &DPADRIGHT),
+    (&CODE_DPAD_L, &DPADLEFT),
+    (&CODE_BUTTON_A, &BUTTONA),
+    (&CODE_BUTTON_B, &BUTTONB),
+    (&CODE_BUTTON_X, &BUTTONX),
+    (&CODE_BUTTON_Y, &BUTTONY),
+    (&CODE_SHOULDER_L, &SHOULDER_L),
+    (&CODE_SHOULDER_R, &SHOULDER_R),
+    (&CODE_THUMB_L, &THUMB_L),
+    (&CODE_THUMB_R, &THUMB_R),
+    (&CODE_BACK, &BACK),
+    (&CODE_LSTICK_D, &LSTICKDOWN),
+    (&CODE_LSTICK_U, &LSTICKUP),
+    (&CODE_LSTICK_R, &LSTICKRIGHT),
+    (&CODE_LSTICK_L, &LSTICKLEFT),
+    (&CODE_WALK, &WALK),
+];
+// Resolves key events into the bindings above; it holds the base bindings'
+// keys, chords and layers so the keyboard hook need only take this one lock.
+pub static KEY_RESOLVER: Mutex<KeyResolver> = Mutex::new(KeyResolver::new());
+pub static SCRIPT: Mutex<String> = Mutex::new(String::new());
+// Key events waiting for the script, recorded only while there is one.
+pub static SCRIPT_ENABLED: AtomicBool = AtomicBool::new(false);
+pub static SCRIPT_EVENTS: Mutex<Vec<(i32, bool)>> = Mutex::new(Vec::new());
+// A change in the script's error, empty once it runs again, waiting for the
+// main thread to pass it on to the front end.
+pub static SCRIPT_ERROR: Mutex<Option<String>> = Mutex::new(None);
 pub static X: AtomicI32 = AtomicI32::new(0);
 pub static Y: AtomicI32 = AtomicI32::new(0);
 
@@ -50,8 +163,54 @@ pub static CODE_SHOULDER_R: AtomicI32 = AtomicI32::new(0);
 pub static CODE_THUMB_R: AtomicI32 = AtomicI32::new(0);
 pub static CODE_THUMB_L: AtomicI32 = AtomicI32::new(0);
 pub static CODE_BACK: AtomicI32 = AtomicI32::new(0);
-pub static LEFT_AUTOFIRE: AtomicBool = AtomicBool::new(false);
-pub static RIGHT_AUTOFIRE: AtomicBool = AtomicBool::new(false);
+pub static CODE_WALK: AtomicI32 = AtomicI32::new(0);
+pub static LEFT_FIRE_MODE: AtomicU8 = AtomicU8::new(common::FireMode::Hold as u8);
+pub static RIGHT_FIRE_MODE: AtomicU8 = AtomicU8::new(common::FireMode::Hold as u8);
+pub static LEFT_AUTOFIRE_RPM: AtomicI32 = AtomicI32::new(800);
+pub static LEFT_AUTOFIRE_DUTY: AtomicI32 = AtomicI32::new(50);
+pub static RIGHT_AUTOFIRE_RPM: AtomicI32 = AtomicI32::new(800);
+pub static RIGHT_AUTOFIRE_DUTY: AtomicI32 = AtomicI32::new(50);
+pub static LEFT_BURST_COUNT: AtomicI32 = AtomicI32::new(3);
+pub static RIGHT_BURST_COUNT: AtomicI32 = AtomicI32::new(3);
+
+// Turbo rate for each button, in presses per minute
+pub static TURBO_START: AtomicI32 = AtomicI32::new(0);
+pub static TURBO_DPAD_U: AtomicI32 = AtomicI32::new(0);
+pub static TURBO_DPAD_D: AtomicI32 = AtomicI32::new(0);
+pub static TURBO_DPAD_L: AtomicI32 = AtomicI32::new(0);
+pub static TURBO_DPAD_R: AtomicI32 = AtomicI32::new(0);
+pub static TURBO_BUTTON_A: AtomicI32 = AtomicI32::new(0);
+pub static TURBO_BUTTON_B: AtomicI32 = AtomicI32::new(0);
+pub static TURBO_BUTTON_X: AtomicI32 = AtomicI32::new(0);
+pub static TURBO_BUTTON_Y: AtomicI32 = AtomicI32::new(0);
+pub static TURBO_SHOULDER_L: AtomicI32 = AtomicI32::new(0);
+pub static TURBO_SHOULDER_R: AtomicI32 = AtomicI32::new(0);
+pub static TURBO_THUMB_L: AtomicI32 = AtomicI32::new(0);
+pub static TURBO_THUMB_R: AtomicI32 = AtomicI32::new(0);
+pub static TURBO_BACK: AtomicI32 = AtomicI32::new(0);
+
+// Activation mode of each gamepad button's binding.
+pub static ACTIVATIONS: Mutex<common::Activations> = Mutex::new(common::Activations {
+    start: DEFAULT_ACTIVATION,
+    dpadu: DEFAULT_ACTIVATION,
+    dpadd: DEFAULT_ACTIVATION,
+    dpadl: DEFAULT_ACTIVATION,
+    dpadr: DEFAULT_ACTIVATION,
+    buttona: DEFAULT_ACTIVATION,
+    buttonb: DEFAULT_ACTIVATION,
+    buttonx: DEFAULT_ACTIVATION,
+    buttony: DEFAULT_ACTIVATION,
+    shoulderl: DEFAULT_ACTIVATION,
+    shoulderr: DEFAULT_ACTIVATION,
+    lthumb: DEFAULT_ACTIVATION,
+    rthumb: DEFAULT_ACTIVATION,
+    back: DEFAULT_ACTIVATION,
+});
+const DEFAULT_ACTIVATION: common::Activation = common::Activation {
+    mode: common::ActivationMode::Hold,
+    millis: 200,
+    alternate: None,
+};
 
 // Recoil compensation functionality
 pub static RECOIL_COMPENSATION_ACTIVE: AtomicBool = AtomicBool::new(false);
@@ -59,6 +218,29 @@ pub static RECOIL_COMPENSATION_VERTICAL: AtomicI32 = AtomicI32::new(0);
 pub static RECOIL_COMPENSATION_SIDEWAYS: AtomicI32 = AtomicI32::new(0);
 pub static RECOIL_IMPULSE_VERTICAL: AtomicI32 = AtomicI32::new(0);
 pub static RECOIL_IMPULSE_DURATION: AtomicI32 = AtomicI32::new(0);
+pub static RECOIL_PATTERN: Mutex<Vec<common::RecoilPoint>> = Mutex::new(Vec::new());
+pub static RECOIL_PATTERN_PER_SHOT: AtomicBool = AtomicBool::new(false);
+pub static RECOIL_ACTIVATION: AtomicU8 = AtomicU8::new(0);
+pub static CODE_RECOIL_MODIFIER: AtomicI32 = AtomicI32::new(0);
+pub static RECOIL_MODIFIER: AtomicBool = AtomicBool::new(false);
+
+// Microseconds on the monotonic controller clock of the oldest input event not
+// yet reflected in a report, or zero if there is none.
+pub static PENDING_INPUT_INSTANT: AtomicU64 = AtomicU64::new(0);
+
+/// Have the controller emit a report now rather than at its next tick.
+pub fn wake_controller() {
+    if let Some(thread) = CONTROLLER_THREAD.get() {
+        thread.unpark();
+    }
+}
+
+/// Note an input event for latency measurement and wake the controller.
+pub fn input_event() {
+    let now = (SystemClock.now().as_micros() as u64).max(1);
+    _ = PENDING_INPUT_INSTANT.compare_exchange(0, now, Ordering::Relaxed, Ordering::Relaxed);
+    wake_controller();
+}
 
 pub fn apply_button_map(map: &common::ButtonMapping) {
     for (control, vcode) in [
@@ -80,30 +262,163 @@ pub fn apply_button_map(map: &common::ButtonMapping) {
         (&CODE_THUMB_R, map.rthumb),
         (&CODE_THUMB_L, map.lthumb),
         (&CODE_BACK, map.back),
+        (&CODE_WALK, map.walk),
     ] {
         control.store(vcode, std::sync::atomic::Ordering::Relaxed);
     }
-    for (control, vcode) in [
-        (&LEFT_AUTOFIRE, map.left_autofire),
-        (&RIGHT_AUTOFIRE, map.right_autofire),
+    for (control, rate) in [
+        (&TURBO_START, map.turbo.start),
+        (&TURBO_DPAD_U, map.turbo.dpadu),
+        (&TURBO_DPAD_D, map.turbo.dpadd),
+        (&TURBO_DPAD_L, map.turbo.dpadl),
+        (&TURBO_DPAD_R, map.turbo.dpadr),
+        (&TURBO_BUTTON_A, map.turbo.buttona),
+        (&TURBO_BUTTON_B, map.turbo.buttonb),
+        (&TURBO_BUTTON_X, map.turbo.buttonx),
+        (&TURBO_BUTTON_Y, map.turbo.buttony),
+        (&TURBO_SHOULDER_L, map.turbo.shoulderl),
+        (&TURBO_SHOULDER_R, map.turbo.shoulderr),
+        (&TURBO_THUMB_L, map.turbo.lthumb),
+        (&TURBO_THUMB_R, map.turbo.rthumb),
+        (&TURBO_BACK, map.turbo.back),
     ] {
-        control.store(vcode, std::sync::atomic::Ordering::Relaxed);
+        control.store(rate, Ordering::Relaxed);
     }
+    *ACTIVATIONS.lock().expect("activations lock poisoned") = map.activation;
+    LEFT_FIRE_MODE.store(map.left_fire_mode as u8, Ordering::Relaxed);
+    RIGHT_FIRE_MODE.store(map.right_fire_mode as u8, Ordering::Relaxed);
+
+    LEFT_AUTOFIRE_RPM.store(map.left_autofire_rpm, Ordering::Relaxed);
+    LEFT_AUTOFIRE_DUTY.store(map.left_autofire_duty, Ordering::Relaxed);
+    RIGHT_AUTOFIRE_RPM.store(map.right_autofire_rpm, Ordering::Relaxed);
+    RIGHT_AUTOFIRE_DUTY.store(map.right_autofire_duty, Ordering::Relaxed);
+    LEFT_BURST_COUNT.store(map.left_burst_count, Ordering::Relaxed);
+    RIGHT_BURST_COUNT.store(map.right_burst_count, Ordering::Relaxed);
 
     RECOIL_COMPENSATION_ACTIVE.store(map.recoil_compensation_active, Ordering::Relaxed);
     RECOIL_COMPENSATION_SIDEWAYS.store(map.recoil_sideways_compensation, Ordering::Relaxed);
     RECOIL_COMPENSATION_VERTICAL.store(map.recoil_vertical_compensation, Ordering::Relaxed);
     RECOIL_IMPULSE_DURATION.store(map.recoil_impulse_duration, Ordering::Relaxed);
     RECOIL_IMPULSE_VERTICAL.store(map.recoil_impulse_vertical, Ordering::Relaxed);
+    *RECOIL_PATTERN.lock().expect("recoil pattern lock poisoned") = map.recoil_pattern.clone();
+    RECOIL_PATTERN_PER_SHOT.store(map.recoil_pattern_per_shot, Ordering::Relaxed);
+    RECOIL_ACTIVATION.store(map.recoil_activation as u8, Ordering::Relaxed);
+    CODE_RECOIL_MODIFIER.store(map.recoil_modifier, Ordering::Relaxed);
+    *WEAPONS.lock().expect("weapons lock poisoned") = map.weapons.clone();
+    select_weapon(map.active_weapon);
+    WEAPON_WHEEL.store(map.weapon_wheel, Ordering::Relaxed);
+    KEY_RESOLVER
+        .lock()
+        .expect("key resolver lock poisoned")
+        .configure(&map.bindings().codes(), &map.chords, &map.layers);
+    SCRIPT_ENABLED.store(!map.script.trim().is_empty(), Ordering::Relaxed);
+    *SCRIPT.lock().expect("script lock poisoned") = map.script.clone();
+    let mut macros = MACROS.lock().expect("macros lock poisoned");
+    if *macros != map.macros {
+        *macros = map.macros.clone();
+        CANCEL_MACROS.store(true, Ordering::Relaxed);
+    }
+    drop(macros);
 
     INTERVAL_MICROS.store(map.sampling_interval, Ordering::Relaxed);
+    STICK_GATE.store(map.stick_gate as u8, Ordering::Relaxed);
+    SOCD_HORIZONTAL.store(map.socd_horizontal as u8, Ordering::Relaxed);
+    SOCD_VERTICAL.store(map.socd_vertical as u8, Ordering::Relaxed);
+    WALK_SPEED.store(map.walk_speed, Ordering::Relaxed);
+    MOVEMENT_RAMP.store(map.movement_ramp, Ordering::Relaxed);
     MOVEMENT_MULTIPLIER.store(
         map.movement_multiplier,
         std::sync::atomic::Ordering::Relaxed,
     );
+    wake_controller();
+}
+
+pub fn left_trigger() -> Trigger {
+    Trigger {
+        mode: RIGHT_FIRE_MODE.load(Ordering::Relaxed).into(),
+        rpm: RIGHT_AUTOFIRE_RPM.load(Ordering::Relaxed),
+        duty: RIGHT_AUTOFIRE_DUTY.load(Ordering::Relaxed),
+        burst: RIGHT_BURST_COUNT.load(Ordering::Relaxed),
+    }
+}
+
+/// Make the preset at `index` the active one, or none if it does not exist.
+pub fn select_weapon(index: Option<usize>) {
+    let weapons = WEAPONS.lock().expect("weapons lock poisoned");
+    let index = index.filter(|ix| *ix < weapons.len());
+    ACTIVE_WEAPON.store(index.map_or(-1, |ix| ix as i32), Ordering::Relaxed);
+    let armament = Armament::new(index.map(|ix| &weapons[ix]));
+    drop(weapons);
+    *ARMAMENT.lock().expect("armament lock poisoned") = Some(Arc::new(armament));
+}
+
+pub fn active_weapon_index() -> Option<usize> {
+    usize::try_from(ACTIVE_WEAPON.load(Ordering::Relaxed)).ok()
+}
+
+/// The settings of the left mouse button, as overridden by the active weapon
+/// preset, if any.
+#[derive(Debug)]
+pub struct Armament {
+    pub right_trigger: Trigger,
+    pub recoil_pattern: RecoilPattern,
+    /// Percentage of the movement rate to use while aiming down sights. Only
+    /// presets have one, so with none active aiming is not slowed.
+    pub ads_sensitivity: i32,
+}
+
+impl Armament {
+    fn new(weapon: Option<&WeaponPreset>) -> Self {
+        match weapon {
+            Some(weapon) => {
+                let right_trigger = Trigger {
+                    mode: weapon.fire_mode,
+                    rpm: weapon.autofire_rpm,
+                    duty: weapon.autofire_duty,
+                    burst: weapon.burst_count,
+                };
+                Armament {
+                    right_trigger,
+                    recoil_pattern: RecoilPattern::new(
+                        &weapon.recoil_pattern,
+                        weapon.recoil_pattern_per_shot,
+                        right_trigger.shot_period(),
+                    ),
+                    ads_sensitivity: weapon.ads_sensitivity,
+                }
+            }
+            None => {
+                let right_trigger = Trigger {
+                    mode: LEFT_FIRE_MODE.load(Ordering::Relaxed).into(),
+                    rpm: LEFT_AUTOFIRE_RPM.load(Ordering::Relaxed),
+                    duty: LEFT_AUTOFIRE_DUTY.load(Ordering::Relaxed),
+                    burst: LEFT_BURST_COUNT.load(Ordering::Relaxed),
+                };
+                Armament {
+                    right_trigger,
+                    recoil_pattern: RecoilPattern::new(
+                        &RECOIL_PATTERN.lock().expect("recoil pattern lock poisoned"),
+                        RECOIL_PATTERN_PER_SHOT.load(Ordering::Relaxed),
+                        right_trigger.shot_period(),
+                    ),
+                    ads_sensitivity: 100,
+                }
+            }
+        }
+    }
+}
+
+/// The armament in effect, as last resolved by `select_weapon`.
+pub fn armament() -> Arc<Armament> {
+    let armament = ARMAMENT.lock().expect("armament lock poisoned").clone();
+    armament.unwrap_or_else(|| {
+        select_weapon(active_weapon_index());
+        self::armament()
+    })
 }
 
 pub fn create_button_map() -> common::ButtonMapping {
+    let resolver = KEY_RESOLVER.lock().expect("key resolver lock poisoned");
     common::ButtonMapping {
         dpadl: CODE_DPAD_L.load(Ordering::Relaxed),
         dpadu: CODE_DPAD_U.load(Ordering::Relaxed),
@@ -123,8 +438,8 @@ pub fn create_button_map() -> common::ButtonMapping {
         lthumb: CODE_THUMB_L.load(Ordering::Relaxed),
         rthumb: CODE_THUMB_R.load(Ordering::Relaxed),
         back: CODE_BACK.load(Ordering::Relaxed),
-        left_autofire: LEFT_AUTOFIRE.load(Ordering::Relaxed),
-        right_autofire: RIGHT_AUTOFIRE.load(Ordering::Relaxed),
+        left_fire_mode: LEFT_FIRE_MODE.load(Ordering::Relaxed).into(),
+        right_fire_mode: RIGHT_FIRE_MODE.load(Ordering::Relaxed).into(),
         movement_multiplier: MOVEMENT_MULTIPLIER.load(Ordering::Relaxed),
         sampling_interval: INTERVAL_MICROS.load(Ordering::Relaxed),
         recoil_compensation_active: RECOIL_COMPENSATION_ACTIVE.load(Ordering::Relaxed),
@@ -132,5 +447,48 @@ pub fn create_button_map() -> common::ButtonMapping {
         recoil_sideways_compensation: RECOIL_COMPENSATION_SIDEWAYS.load(Ordering::Relaxed),
         recoil_impulse_vertical: RECOIL_IMPULSE_VERTICAL.load(Ordering::Relaxed),
         recoil_impulse_duration: RECOIL_IMPULSE_DURATION.load(Ordering::Relaxed),
+        recoil_activation: RECOIL_ACTIVATION.load(Ordering::Relaxed).into(),
+        recoil_modifier: CODE_RECOIL_MODIFIER.load(Ordering::Relaxed),
+        stick_gate: STICK_GATE.load(Ordering::Relaxed).into(),
+        socd_horizontal: SOCD_HORIZONTAL.load(Ordering::Relaxed).into(),
+        socd_vertical: SOCD_VERTICAL.load(Ordering::Relaxed).into(),
+        walk: CODE_WALK.load(Ordering::Relaxed),
+        walk_speed: WALK_SPEED.load(Ordering::Relaxed),
+        movement_ramp: MOVEMENT_RAMP.load(Ordering::Relaxed),
+        left_autofire_rpm: LEFT_AUTOFIRE_RPM.load(Ordering::Relaxed),
+        left_autofire_duty: LEFT_AUTOFIRE_DUTY.load(Ordering::Relaxed),
+        right_autofire_rpm: RIGHT_AUTOFIRE_RPM.load(Ordering::Relaxed),
+        right_autofire_duty: RIGHT_AUTOFIRE_DUTY.load(Ordering::Relaxed),
+        left_burst_count: LEFT_BURST_COUNT.load(Ordering::Relaxed),
+        right_burst_count: RIGHT_BURST_COUNT.load(Ordering::Relaxed),
+        recoil_pattern: RECOIL_PATTERN
+            .lock()
+            .expect("recoil pattern lock poisoned")
+            .clone(),
+        recoil_pattern_per_shot: RECOIL_PATTERN_PER_SHOT.load(Ordering::Relaxed),
+        weapons: WEAPONS.lock().expect("weapons lock poisoned").clone(),
+        active_weapon: active_weapon_index(),
+        weapon_wheel: WEAPON_WHEEL.load(Ordering::Relaxed),
+        macros: MACROS.lock().expect("macros lock poisoned").clone(),
+        chords: resolver.chords().to_vec(),
+        layers: resolver.layers().to_vec(),
+        script: SCRIPT.lock().expect("script lock poisoned").clone(),
+        activation: *ACTIVATIONS.lock().expect("activations lock poisoned"),
+        turbo: common::TurboRates {
+            start: TURBO_START.load(Ordering::Relaxed),
+            dpadu: TURBO_DPAD_U.load(Ordering::Relaxed),
+            dpadd: TURBO_DPAD_D.load(Ordering::Relaxed),
+            dpadl: TURBO_DPAD_L.load(Ordering::Relaxed),
+            dpadr: TURBO_DPAD_R.load(Ordering::Relaxed),
+            buttona: TURBO_BUTTON_A.load(Ordering::Relaxed),
+            buttonb: TURBO_BUTTON_B.load(Ordering::Relaxed),
+            buttonx: TURBO_BUTTON_X.load(Ordering::Relaxed),
+            buttony: TURBO_BUTTON_Y.load(Ordering::Relaxed),
+            shoulderl: TURBO_SHOULDER_L.load(Ordering::Relaxed),
+            shoulderr: TURBO_SHOULDER_R.load(Ordering::Relaxed),
+            lthumb: TURBO_THUMB_L.load(Ordering::Relaxed),
+            rthumb: TURBO_THUMB_R.load(Ordering::Relaxed),
+            back: TURBO_BACK.load(Ordering::Relaxed),
+        },
     }
 }
@@ -0,0 +1,92 @@
+use common::StickGate;
+
+// Full deflection on either axis. We use the positive extreme for both
+// directions so that the gate is symmetric.
+const RADIUS: f64 = i16::MAX as f64;
+
+/// Confine a raw stick vector to the given gate and convert it to the
+/// gamepad's axis range.
+pub fn apply_gate(gate: StickGate, x: i32, y: i32) -> (i16, i16) {
+    let (x, y) = (x as f64, y as f64);
+    let magnitude = x.hypot(y);
+    let limit = match gate {
+        StickGate::Square => {
+            return (clamp_axis(x), clamp_axis(y));
+        }
+        StickGate::Circle => RADIUS,
+        StickGate::Octagon => {
+            // Distance from the centre to the octagon's edge along the
+            // direction of travel; the vertices lie every 45 degrees.
+            let sector = std::f64::consts::FRAC_PI_4;
+            let apothem = RADIUS * (sector / 2.).cos();
+            let theta = y.atan2(x).rem_euclid(sector);
+            apothem / (theta - sector / 2.).cos()
+        }
+    };
+
+    if magnitude <= limit {
+        (clamp_axis(x), clamp_axis(y))
+    } else {
+        let scale = limit / magnitude;
+        (clamp_axis(x * scale), clamp_axis(y * scale))
+    }
+}
+
+fn clamp_axis(v: f64) -> i16 {
+    v.round().clamp(-RADIUS, RADIUS) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX: i32 = i16::MAX as i32;
+
+    fn magnitude((x, y): (i16, i16)) -> f64 {
+        (x as f64).hypot(y as f64)
+    }
+
+    #[test]
+    fn square_gate_lets_diagonals_reach_the_corner() {
+        assert_eq!(
+            apply_gate(StickGate::Square, MAX, MAX),
+            (i16::MAX, i16::MAX)
+        );
+        assert_eq!(
+            apply_gate(StickGate::Square, -4 * MAX, 2 * MAX),
+            (-i16::MAX, i16::MAX)
+        );
+    }
+
+    #[test]
+    fn circle_gate_equalises_cardinal_and_diagonal_magnitude() {
+        let cardinal = apply_gate(StickGate::Circle, 0, MAX);
+        let diagonal = apply_gate(StickGate::Circle, MAX, MAX);
+        assert_eq!(cardinal, (0, i16::MAX));
+        assert!((magnitude(diagonal) - RADIUS).abs() < 1.);
+        assert_eq!(diagonal.0, diagonal.1);
+    }
+
+    #[test]
+    fn circle_gate_leaves_small_vectors_alone() {
+        assert_eq!(apply_gate(StickGate::Circle, 1000, -2000), (1000, -2000));
+    }
+
+    #[test]
+    fn octagon_gate_reaches_full_deflection_on_vertices() {
+        for (x, y) in [(MAX, 0), (0, -MAX), (MAX, MAX), (-MAX, MAX)] {
+            let out = apply_gate(StickGate::Octagon, 2 * x, 2 * y);
+            assert!((magnitude(out) - RADIUS).abs() < 1.5, "{:?}", out);
+        }
+    }
+
+    #[test]
+    fn octagon_gate_clamps_between_vertices_to_the_edge() {
+        let angle = std::f64::consts::PI / 8.;
+        let x = (2. * RADIUS * angle.cos()) as i32;
+        let y = (2. * RADIUS * angle.sin()) as i32;
+        let out = apply_gate(StickGate::Octagon, x, y);
+        let apothem = RADIUS * angle.cos();
+        assert!((magnitude(out) - apothem).abs() < 1.5, "{:?}", out);
+    }
+}
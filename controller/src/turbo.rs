@@ -0,0 +1,61 @@
+use crate::firing::Trigger;
+use common::FireMode;
+use std::time::Duration;
+
+/// Auto-repeat for a single gamepad button.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Turbo {
+    pressed_since: Option<Duration>,
+}
+
+impl Turbo {
+    /// Returns whether the button should read as pressed at `now`, and whether
+    /// that will change with time alone. A rate of zero turns turbo off.
+    pub fn update(&mut self, pressed: bool, rpm: i32, now: Duration) -> (bool, bool) {
+        if !pressed {
+            self.pressed_since = None;
+            return (false, false);
+        }
+        let since = *self.pressed_since.get_or_insert(now);
+        if rpm <= 0 {
+            return (true, false);
+        }
+
+        let pulses = Trigger {
+            mode: FireMode::Auto,
+            rpm,
+            duty: 50,
+            burst: 0,
+        };
+        (pulses.value(now.saturating_sub(since)) != 0, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn turbo_repeats_from_the_first_press() {
+        // 600 RPM: 50 ms down, 50 ms up.
+        let mut turbo = Turbo::default();
+        let states: Vec<bool> = [1000, 1049, 1050, 1099, 1100]
+            .iter()
+            .map(|t| turbo.update(true, 600, ms(*t)).0)
+            .collect();
+        assert_eq!(states, vec![true, true, false, false, true]);
+        assert_eq!(turbo.update(false, 600, ms(1120)), (false, false));
+        assert_eq!(turbo.update(true, 600, ms(1130)), (true, true));
+    }
+
+    #[test]
+    fn zero_rate_is_a_plain_hold() {
+        let mut turbo = Turbo::default();
+        assert_eq!(turbo.update(true, 0, ms(0)), (true, false));
+        assert_eq!(turbo.update(true, 0, ms(5000)), (true, false));
+    }
+}
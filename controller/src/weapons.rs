@@ -0,0 +1,56 @@
+use common::WeaponPreset;
+
+/// The preset selected by pressing `code`, if it is any preset's hotkey.
+pub fn weapon_for_key(weapons: &[WeaponPreset], code: i32) -> Option<usize> {
+    if code == 0 {
+        return None;
+    }
+    weapons.iter().position(|w| w.hotkey == code)
+}
+
+/// Step through the presets, wrapping at either end. With no preset active
+/// the first step forward selects the first preset and a step back the last.
+pub fn cycle_weapon(active: Option<usize>, count: usize, forward: bool) -> Option<usize> {
+    if count == 0 {
+        return None;
+    }
+    Some(match (active, forward) {
+        (None, true) => 0,
+        (None, false) => count - 1,
+        (Some(ix), true) => (ix + 1) % count,
+        (Some(ix), false) => (ix + count - 1) % count,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hotkeys_select_their_preset() {
+        let weapons = vec![
+            WeaponPreset {
+                hotkey: 0x31,
+                ..Default::default()
+            },
+            WeaponPreset {
+                hotkey: 0x32,
+                ..Default::default()
+            },
+            WeaponPreset::default(),
+        ];
+        assert_eq!(weapon_for_key(&weapons, 0x32), Some(1));
+        assert_eq!(weapon_for_key(&weapons, 0x33), None);
+        // An unbound hotkey must not match the "None" key code.
+        assert_eq!(weapon_for_key(&weapons, 0), None);
+    }
+
+    #[test]
+    fn cycling_wraps_in_both_directions() {
+        assert_eq!(cycle_weapon(None, 3, true), Some(0));
+        assert_eq!(cycle_weapon(None, 3, false), Some(2));
+        assert_eq!(cycle_weapon(Some(2), 3, true), Some(0));
+        assert_eq!(cycle_weapon(Some(0), 3, false), Some(2));
+        assert_eq!(cycle_weapon(Some(1), 0, true), None);
+    }
+}
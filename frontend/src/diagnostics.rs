@@ -0,0 +1,139 @@
+use eframe::egui;
+use egui_extras::{Size, TableBuilder};
+use std::time::{Duration, Instant};
+use windows::{
+    s,
+    Win32::{
+        Foundation::{LPARAM, WPARAM},
+        System::DataExchange::COPYDATASTRUCT,
+        UI::WindowsAndMessaging::{FindWindowA, SendMessageA, WM_COPYDATA},
+    },
+};
+
+use crate::exit_with_error;
+
+// How often to ask the controller for fresh numbers while the panel is open.
+const REFRESH: Duration = Duration::from_millis(500);
+
+pub struct Diagnostics {
+    pub open: bool,
+    rx: crossbeam::channel::Receiver<common::Metrics>,
+    metrics: common::Metrics,
+    last_query: Option<Instant>,
+}
+
+impl Diagnostics {
+    pub fn new(rx: crossbeam::channel::Receiver<common::Metrics>) -> Self {
+        Diagnostics {
+            open: false,
+            rx,
+            metrics: common::Metrics::default(),
+            last_query: None,
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        if !self.open {
+            self.last_query = None;
+            return;
+        }
+
+        if self.last_query.map_or(true, |q| q.elapsed() >= REFRESH) {
+            query_metrics();
+            self.last_query = Some(Instant::now());
+        }
+        while let Ok(metrics) = self.rx.try_recv() {
+            self.metrics = metrics;
+        }
+        ctx.request_repaint_after(REFRESH);
+
+        let metrics = &self.metrics;
+        egui::Window::new("Diagnostics")
+            .open(&mut self.open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("Rates").show(ui, |ui| {
+                    ui.label("Reports");
+                    ui.label(format!("{}", metrics.reports));
+                    ui.end_row();
+                    ui.label("Report rate");
+                    ui.label(format!("{:.0} Hz", metrics.report_rate));
+                    ui.end_row();
+                    ui.label("Tick overruns");
+                    ui.label(format!("{}", metrics.overruns));
+                    ui.end_row();
+                    ui.label("Saturated stick");
+                    ui.label(format!("{:.1} %", metrics.saturated_ratio() * 100.));
+                    ui.end_row();
+                    ui.label("Dropped motion");
+                    ui.label(format!("{} counts", metrics.dropped_motion));
+                    ui.end_row();
+                    ui.label("Worst latency");
+                    ui.label(format!("{} \u{3bc}sec", metrics.latency_max_micros));
+                    ui.end_row();
+                });
+                ui.separator();
+                ui.label("Input to output latency");
+                let total: u64 = metrics.latency_histogram.iter().sum();
+                TableBuilder::new(ui)
+                    .column(Size::exact(100.))
+                    .column(Size::exact(160.))
+                    .body(|mut body| {
+                        for (ix, count) in metrics.latency_histogram.iter().enumerate() {
+                            body.row(18.0, |mut row| {
+                                row.col(|ui| {
+                                    ui.label(bucket_label(ix));
+                                });
+                                row.col(|ui| {
+                                    let fraction = if total == 0 {
+                                        0.
+                                    } else {
+                                        *count as f32 / total as f32
+                                    };
+                                    ui.add(
+                                        egui::ProgressBar::new(fraction).text(format!("{}", count)),
+                                    );
+                                });
+                            });
+                        }
+                    });
+            });
+    }
+}
+
+fn bucket_label(ix: usize) -> String {
+    match common::LATENCY_BUCKETS.get(ix) {
+        Some(bound) => format!("\u{2264} {} \u{3bc}sec", bound),
+        None => format!(
+            "> {} \u{3bc}sec",
+            common::LATENCY_BUCKETS[common::LATENCY_BUCKETS.len() - 1]
+        ),
+    }
+}
+
+fn query_metrics() {
+    unsafe {
+        let hwui = FindWindowA(s!("serf-message-window"), s!("serf-controller"));
+        if hwui.0 == 0 {
+            exit_with_error(anyhow::anyhow!(
+                "Could not find message sink for back end controller"
+            ));
+        }
+        let copydata = COPYDATASTRUCT {
+            dwData: common::CopyTypes::QueryMetrics as usize,
+            cbData: 0,
+            lpData: std::ptr::null_mut(),
+        };
+        let res = SendMessageA(
+            hwui,
+            WM_COPYDATA,
+            WPARAM(0),
+            LPARAM(&copydata as *const COPYDATASTRUCT as isize),
+        );
+        if res.0 != 1 {
+            exit_with_error(anyhow::anyhow!(
+                "Failed dispatch message to sink for back end controller"
+            ));
+        }
+    }
+}
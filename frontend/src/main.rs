@@ -3,9 +3,13 @@ use anyhow::Result;
 use image::GenericImageView;
 use log::error;
 
+mod diagnostics;
+mod share;
 mod ui;
 use crossbeam::channel::*;
+use diagnostics::Diagnostics;
 use once_cell::sync::OnceCell;
+use share::Sharing;
 use ui::*;
 use windows::{
     core::*,
@@ -19,6 +23,8 @@ use windows::{
 
 static CONTEXT: OnceCell<eframe::egui::Context> = OnceCell::new();
 static TX: OnceCell<Sender<common::ButtonMapping>> = OnceCell::new();
+static METRICS_TX: OnceCell<Sender<common::Metrics>> = OnceCell::new();
+static SCRIPT_TX: OnceCell<Sender<String>> = OnceCell::new();
 
 fn exit_with_error(e: anyhow::Error) {
     unsafe {
@@ -38,11 +44,48 @@ extern "system" fn wndproc(window: HWND, message: u32, wparam: WPARAM, lparam: L
         match message {
             WM_COPYDATA => {
                 let pdata: *const COPYDATASTRUCT = lparam.0 as *const u8 as *const COPYDATASTRUCT;
-                let pbmap = (*pdata).lpData as *mut common::ButtonMapping;
-                TX.get()
-                    .expect("TX hasn't been initialized.")
-                    .send((*pbmap).clone())
-                    .expect("Failed to send updated button map to UI");
+                if (*pdata).dwData == common::CopyTypes::Metrics as usize {
+                    let pmetrics = (*pdata).lpData as *mut common::Metrics;
+                    METRICS_TX
+                        .get()
+                        .expect("METRICS_TX hasn't been initialized.")
+                        .send(*pmetrics)
+                        .expect("Failed to send metrics to UI");
+                    return LRESULT(1);
+                }
+                if (*pdata).dwData == common::CopyTypes::ScriptError as usize {
+                    // An empty message clears the error, and may come without data.
+                    let error = match (*pdata).cbData {
+                        0 => String::new(),
+                        len => String::from_utf8_lossy(std::slice::from_raw_parts(
+                            (*pdata).lpData as *const u8,
+                            len as usize,
+                        ))
+                        .into_owned(),
+                    };
+                    SCRIPT_TX
+                        .get()
+                        .expect("SCRIPT_TX hasn't been initialized.")
+                        .send(error)
+                        .expect("Failed to send script error to UI");
+                    CONTEXT
+                        .get()
+                        .expect("Context hasn't been initialized.")
+                        .request_repaint();
+                    return LRESULT(1);
+                }
+                let bytes = std::slice::from_raw_parts(
+                    (*pdata).lpData as *const u8,
+                    (*pdata).cbData as usize,
+                );
+                match common::ButtonMapping::decode(bytes) {
+                    Ok(map) => TX
+                        .get()
+                        .expect("TX hasn't been initialized.")
+                        .send(map)
+                        .expect("Failed to send updated button map to UI"),
+                    Err(e) => exit_with_error(e),
+                }
                 CONTEXT
                     .get()
                     .expect("Context hasn't been initialized.")
@@ -71,8 +114,63 @@ fn get_icon_data() -> Option<eframe::IconData> {
     })
 }
 
+// The value of `--config <path>` or `--config=<path>`, if given.
+fn config_flag() -> Result<Option<std::path::PathBuf>> {
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return match args.next() {
+                Some(path) => Ok(Some(path.into())),
+                None => Err(anyhow::anyhow!("--config needs a path")),
+            };
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Ok(Some(path.into()));
+        }
+    }
+    Ok(None)
+}
+
+// Offer to restore the newest readable backup of a configuration which
+// failed to load, rather than giving up.
+fn recover_configuration(
+    path: &std::path::Path,
+    error: anyhow::Error,
+) -> Result<common::Configuration> {
+    let Some(backup) = common::Configuration::newest_valid_backup(path) else {
+        return Err(error);
+    };
+    let message = format!(
+        "Could not read the configuration at {}:\n{:?}\n\nRestore it from the backup at {}?\0",
+        path.display(),
+        error,
+        backup.display()
+    );
+    let answer = unsafe {
+        MessageBoxA(
+            None,
+            Some(PCSTR::from_raw(message.as_ptr())),
+            s!("Serf configuration"),
+            MB_YESNO | MB_ICONWARNING,
+        )
+    };
+    if answer != IDYES {
+        return Err(error);
+    }
+    common::Configuration::restore(path, &backup)
+}
+
 fn run_frontend() -> Result<()> {
-    let configuration = common::Configuration::load()?;
+    let path = common::Configuration::locate(config_flag()?)?;
+    let configuration = match common::Configuration::load(&path) {
+        Ok(configuration) => configuration,
+        Err(e) => recover_configuration(&path, e)?,
+    };
+    // Problems do not stop the configuration loading; they show in the UI.
+    let problems = common::validate(&configuration);
+    for problem in &problems {
+        log::warn!("{}", problem);
+    }
     unsafe {
         let instance = GetModuleHandleA(None)?;
         debug_assert!(instance.0 != 0);
@@ -110,7 +208,7 @@ fn run_frontend() -> Result<()> {
 
     // Show the configuration screen
     let options = eframe::NativeOptions {
-        initial_window_size: Some(eframe::egui::vec2(460.0, 510.0)),
+        initial_window_size: Some(eframe::egui::vec2(460.0, 785.0)),
         follow_system_theme: false,
         default_theme: eframe::Theme::Dark,
         icon_data,
@@ -121,11 +219,40 @@ fn run_frontend() -> Result<()> {
     let (tx, rx) = unbounded::<common::ButtonMapping>();
     TX.set(tx)
         .map_err(|_| anyhow::anyhow!("TX already initialized."))?;
+    let (metrics_tx, metrics_rx) = unbounded::<common::Metrics>();
+    METRICS_TX
+        .set(metrics_tx)
+        .map_err(|_| anyhow::anyhow!("METRICS_TX already initialized."))?;
+    let (script_tx, script_rx) = unbounded::<String>();
+    SCRIPT_TX
+        .set(script_tx)
+        .map_err(|_| anyhow::anyhow!("SCRIPT_TX already initialized."))?;
     let app = Box::new(SerfApp {
         active_game_index: 0,
+        saved_games: configuration.games.clone(),
+        validated_games: configuration.games.clone(),
+        problems,
         configuration,
         previous: common::ButtonMapping::default(),
         rx: rx,
+        diagnostics: Diagnostics::new(metrics_rx),
+        sharing: Sharing::new(),
+        show_turbo: false,
+        show_recoil_pattern: false,
+        show_weapons: false,
+        show_macros: false,
+        show_activation: false,
+        show_layers: false,
+        show_script: false,
+        show_inheritance: false,
+        script_rx,
+        script_error: String::new(),
+        watcher: common::ConfigWatcher::new(&path),
+        last_reload_check: std::time::Instant::now(),
+        reload_error: String::new(),
+        changed_on_disk: false,
+        save_error: String::new(),
+        name_edit: String::new(),
     });
     eframe::run_native(
         "Serf - the console peasants are revolting",
@@ -0,0 +1,157 @@
+use common::{Configuration, OnConflict, Profile};
+use eframe::egui;
+use std::path::Path;
+
+/// Exporting the active game to a profile file, and importing others'.
+pub struct Sharing {
+    pub open: bool,
+    path: String,
+    author: String,
+    // The outcome of the last export or import.
+    status: Result<String, String>,
+    // A profile waiting on the user to decide what to do about a game of the
+    // same name.
+    conflict: Option<Profile>,
+}
+
+impl Default for Sharing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sharing {
+    pub fn new() -> Self {
+        Sharing {
+            open: false,
+            path: String::new(),
+            author: String::new(),
+            status: Ok(String::new()),
+            conflict: None,
+        }
+    }
+
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        configuration: &mut Configuration,
+        active_game_index: &mut usize,
+    ) {
+        let Sharing {
+            open,
+            path,
+            author,
+            status,
+            conflict,
+        } = self;
+        if path.is_empty() {
+            let dir = configuration.path().parent().unwrap_or(Path::new(""));
+            *path = dir.join("profile.toml").display().to_string();
+        }
+
+        egui::Window::new("Share")
+            .open(open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label("Profiles hold one game's controls, as TOML, YAML or JSON by extension.");
+                egui::Grid::new("Profile").show(ui, |ui| {
+                    ui.label("File");
+                    ui.add(egui::TextEdit::singleline(path).desired_width(300.));
+                    ui.end_row();
+                    ui.label("Author");
+                    ui.add(egui::TextEdit::singleline(author).desired_width(300.));
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("Export")
+                        .on_hover_text("Write the active game to the file")
+                        .clicked()
+                    {
+                        let game = &configuration.games[*active_game_index];
+                        *status = Profile::new(game, author)
+                            .write(Path::new(path))
+                            .map(|_| format!("Exported '{}'", game.name))
+                            .map_err(|e| format!("{:#}", e));
+                    }
+                    if ui
+                        .add_enabled(conflict.is_none(), egui::Button::new("Import"))
+                        .on_hover_text("Add the game from the file")
+                        .clicked()
+                    {
+                        match Profile::read(Path::new(path)) {
+                            Ok(profile) if configuration.game_named(&profile.game).is_some() => {
+                                *conflict = Some(profile)
+                            }
+                            Ok(profile) => {
+                                *status = import(
+                                    configuration,
+                                    active_game_index,
+                                    profile,
+                                    OnConflict::Refuse,
+                                )
+                            }
+                            Err(e) => *status = Err(format!("{:#}", e)),
+                        }
+                    }
+                });
+
+                if let Some(profile) = conflict.take() {
+                    ui.separator();
+                    ui.label(format!(
+                        "There is already a game called '{}'.",
+                        profile.game
+                    ));
+                    if !profile.author.is_empty() {
+                        ui.weak(format!(
+                            "This one is by {}, from {}.",
+                            profile.author, profile.created
+                        ));
+                    }
+                    ui.horizontal(|ui| {
+                        let choice = if ui.button("Replace it").clicked() {
+                            Some(OnConflict::Replace)
+                        } else if ui.button("Keep both").clicked() {
+                            Some(OnConflict::Rename)
+                        } else {
+                            None
+                        };
+                        if let Some(choice) = choice {
+                            *status =
+                                import(configuration, active_game_index, profile.clone(), choice);
+                        } else if !ui.button("Cancel").clicked() {
+                            *conflict = Some(profile.clone());
+                        }
+                    });
+                }
+
+                match status {
+                    Ok(message) if !message.is_empty() => {
+                        ui.label(message.as_str());
+                    }
+                    Err(error) => {
+                        ui.colored_label(egui::Color32::RED, error.as_str());
+                    }
+                    _ => {}
+                }
+            });
+    }
+}
+
+// Import `profile` and make it the active game. The configuration still needs
+// saving to keep it.
+fn import(
+    configuration: &mut Configuration,
+    active_game_index: &mut usize,
+    profile: Profile,
+    on_conflict: OnConflict,
+) -> Result<String, String> {
+    let ix = configuration
+        .import(profile, on_conflict)
+        .map_err(|e| format!("{:#}", e))?;
+    *active_game_index = ix;
+    Ok(format!(
+        "Imported '{}'; save to keep it",
+        configuration.games[ix].name
+    ))
+}
@@ -1,6 +1,7 @@
 use common::vkey::*;
 use eframe::egui;
 use egui_extras::{Size, TableBuilder};
+use std::time::{Duration, Instant};
 use windows::{
     s,
     Win32::{
@@ -10,13 +11,92 @@ use windows::{
     },
 };
 
+use crate::diagnostics::Diagnostics;
 use crate::exit_with_error;
+use crate::share::Sharing;
+
+// How often to look for changes made to the configuration file by hand.
+const RELOAD_POLL: Duration = Duration::from_secs(1);
 
 pub struct SerfApp {
     pub active_game_index: usize,
     pub configuration: common::Configuration,
     pub previous: common::ButtonMapping,
     pub rx: crossbeam::channel::Receiver<common::ButtonMapping>,
+    pub diagnostics: Diagnostics,
+    pub sharing: Sharing,
+    pub show_turbo: bool,
+    pub show_recoil_pattern: bool,
+    pub show_weapons: bool,
+    pub show_macros: bool,
+    pub show_activation: bool,
+    pub show_layers: bool,
+    pub show_script: bool,
+    pub show_inheritance: bool,
+    pub script_rx: crossbeam::channel::Receiver<String>,
+    /// Why the controller stopped the script, or empty while it runs.
+    pub script_error: String,
+    pub watcher: common::ConfigWatcher,
+    pub last_reload_check: Instant,
+    /// Why the configuration file could not be reloaded after it changed, or
+    /// empty.
+    pub reload_error: String,
+    /// The games as last loaded or saved, to tell whether there are edits
+    /// which are not saved yet.
+    pub saved_games: Vec<common::Game>,
+    /// The file changed while there were unsaved edits, so rather than
+    /// losing them the user chooses whether to reload.
+    pub changed_on_disk: bool,
+    /// What `common::validate` found in the games as they were when last
+    /// checked, which is only done again once they change.
+    pub problems: Vec<common::Diagnostic>,
+    pub validated_games: Vec<common::Game>,
+    /// Why the last save was refused, or empty.
+    pub save_error: String,
+    /// The active game's name as typed, which may be another game's until
+    /// typing goes on.
+    pub name_edit: String,
+}
+
+impl SerfApp {
+    fn dirty(&self) -> bool {
+        self.configuration.games != self.saved_games
+    }
+
+    // Load the configuration file again, whether to revert or to pick up
+    // edits made outside the UI. Should the file not load, the current
+    // configuration stays in use.
+    fn reload(&mut self) {
+        let path = self.configuration.path().to_path_buf();
+        let result = common::Configuration::load(&path).and_then(|configuration| {
+            if configuration.games.is_empty() {
+                anyhow::bail!("The configuration has no games");
+            }
+            Ok(configuration)
+        });
+        // Loading rewrites the file if it needed upgrading.
+        self.watcher.mark();
+        match result {
+            Ok(configuration) => {
+                // Stay with the same game if it is still there.
+                let name = &self.configuration.games[self.active_game_index].name;
+                self.active_game_index = configuration
+                    .games
+                    .iter()
+                    .position(|game| game.name == *name)
+                    .unwrap_or(0);
+                for problem in common::validate(&configuration) {
+                    log::warn!("{}", problem);
+                }
+                self.saved_games = configuration.games.clone();
+                self.configuration = configuration;
+                self.reload_error.clear();
+                self.changed_on_disk = false;
+                log::info!("Reloaded configuration from {}", path.display());
+            }
+            Err(e) => self.reload_error = format!("{:#}", e),
+        }
+    }
 }
 
 fn selection_dropdown(label: &str, variable: &mut i32, ui: &mut egui::Ui) {
@@ -32,6 +112,634 @@ fn selection_dropdown(label: &str, variable: &mut i32, ui: &mut egui::Ui) {
     });
 }
 
+fn mode_dropdown<T: Copy + PartialEq>(
+    label: &str,
+    variable: &mut T,
+    options: &[T],
+    text: fn(&T) -> &'static str,
+    ui: &mut egui::Ui,
+) {
+    ui.horizontal(|ui| {
+        ui.add_sized([100., 20.], egui::Label::new(label));
+        egui::ComboBox::from_id_source(label)
+            .selected_text(text(variable))
+            .show_ui(ui, |ui| {
+                for option in options {
+                    ui.selectable_value(variable, *option, text(option));
+                }
+            });
+    });
+}
+
+fn turbo_window(rates: &mut common::TurboRates, open: &mut bool, ctx: &egui::Context) {
+    egui::Window::new("Turbo")
+        .open(open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.label("Presses per minute while held; zero turns turbo off.");
+            egui::Grid::new("Turbo rates").show(ui, |ui| {
+                for (label, rate) in [
+                    ("Start", &mut rates.start),
+                    ("DPad Up", &mut rates.dpadu),
+                    ("DPad Down", &mut rates.dpadd),
+                    ("DPad Left", &mut rates.dpadl),
+                    ("DPad Right", &mut rates.dpadr),
+                    ("A", &mut rates.buttona),
+                    ("B", &mut rates.buttonb),
+                    ("X", &mut rates.buttonx),
+                    ("Y", &mut rates.buttony),
+                    ("Left shoulder", &mut rates.shoulderl),
+                    ("Right shoulder", &mut rates.shoulderr),
+                    ("Left thumb", &mut rates.lthumb),
+                    ("Right thumb", &mut rates.rthumb),
+                    ("Back", &mut rates.back),
+                ] {
+                    ui.label(label);
+                    ui.add(
+                        egui::Slider::new(rate, 0..=1200)
+                            .step_by(10.)
+                            .suffix(" rpm")
+                            .integer(),
+                    );
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+fn activation_window(activations: &mut common::Activations, open: &mut bool, ctx: &egui::Context) {
+    egui::Window::new("Activation")
+        .open(open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.label("The time is the tap length, long press threshold or double tap window.");
+            egui::Grid::new("Activations").show(ui, |ui| {
+                ui.label("Button");
+                ui.label("Mode");
+                ui.label("Time");
+                ui.label("Alternate");
+                ui.end_row();
+                for (label, activation) in [
+                    ("Start", &mut activations.start),
+                    ("DPad Up", &mut activations.dpadu),
+                    ("DPad Down", &mut activations.dpadd),
+                    ("DPad Left", &mut activations.dpadl),
+                    ("DPad Right", &mut activations.dpadr),
+                    ("A", &mut activations.buttona),
+                    ("B", &mut activations.buttonb),
+                    ("X", &mut activations.buttonx),
+                    ("Y", &mut activations.buttony),
+                    ("Left shoulder", &mut activations.shoulderl),
+                    ("Right shoulder", &mut activations.shoulderr),
+                    ("Left thumb", &mut activations.lthumb),
+                    ("Right thumb", &mut activations.rthumb),
+                    ("Back", &mut activations.back),
+                ] {
+                    ui.label(label);
+                    egui::ComboBox::from_id_source((label, "mode"))
+                        .selected_text(activation.mode.label())
+                        .show_ui(ui, |ui| {
+                            for mode in common::ActivationMode::ALL {
+                                ui.selectable_value(&mut activation.mode, mode, mode.label());
+                            }
+                        });
+                    ui.add_enabled(
+                        activation.mode != common::ActivationMode::Hold
+                            && activation.mode != common::ActivationMode::Toggle,
+                        egui::DragValue::new(&mut activation.millis)
+                            .clamp_range(10..=2000)
+                            .suffix(" ms"),
+                    );
+                    ui.add_enabled_ui(
+                        activation.mode == common::ActivationMode::LongPress
+                            || activation.mode == common::ActivationMode::DoubleTap,
+                        |ui| {
+                            egui::ComboBox::from_id_source((label, "alternate"))
+                                .selected_text(
+                                    activation.alternate.map_or("Same button", |o| o.label()),
+                                )
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(
+                                        &mut activation.alternate,
+                                        None,
+                                        "Same button",
+                                    );
+                                    for output in common::GamepadOutput::ALL {
+                                        ui.selectable_value(
+                                            &mut activation.alternate,
+                                            Some(output),
+                                            output.label(),
+                                        );
+                                    }
+                                });
+                        },
+                    );
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+fn recoil_pattern_window(
+    controls: &mut common::ButtonMapping,
+    open: &mut bool,
+    ctx: &egui::Context,
+) {
+    egui::Window::new("Recoil pattern")
+        .open(open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            recoil_pattern_editor(
+                &mut controls.recoil_pattern,
+                &mut controls.recoil_pattern_per_shot,
+                ui,
+            );
+        });
+}
+
+fn recoil_pattern_editor(
+    pattern: &mut Vec<common::RecoilPoint>,
+    per_shot: &mut bool,
+    ui: &mut egui::Ui,
+) {
+    ui.checkbox(
+        per_shot,
+        "One point per shot, at the left trigger's fire rate",
+    );
+    ui.label("Offsets are % of stick deflection, interpolated between points.");
+    let per_shot = *per_shot;
+    let mut remove = None;
+    egui::Grid::new("Recoil points").show(ui, |ui| {
+        ui.label(if per_shot { "Shot" } else { "Time" });
+        ui.label("\u{2194}");
+        ui.label("\u{2195}");
+        ui.end_row();
+        for (ix, point) in pattern.iter_mut().enumerate() {
+            if per_shot {
+                ui.label(format!("{}", ix + 1));
+            } else {
+                ui.add(
+                    egui::DragValue::new(&mut point.time)
+                        .clamp_range(0..=10000)
+                        .suffix(" ms"),
+                );
+            }
+            ui.add(egui::DragValue::new(&mut point.dx).clamp_range(-50..=50));
+            ui.add(egui::DragValue::new(&mut point.dy).clamp_range(-50..=50));
+            if ui.button("\u{274c}").on_hover_text("Remove").clicked() {
+                remove = Some(ix);
+            }
+            ui.end_row();
+        }
+    });
+    if let Some(ix) = remove {
+        pattern.remove(ix);
+    }
+    if ui.button("\u{2795} Add point").clicked() {
+        let last = pattern.last().copied().unwrap_or_default();
+        pattern.push(common::RecoilPoint {
+            time: last.time + 100,
+            ..last
+        });
+    }
+}
+
+fn weapons_window(controls: &mut common::ButtonMapping, open: &mut bool, ctx: &egui::Context) {
+    egui::Window::new("Weapons")
+        .open(open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.label("While a preset is active it replaces the left mouse button's settings.");
+            ui.checkbox(&mut controls.weapon_wheel, "Mouse wheel cycles presets");
+            ui.horizontal(|ui| {
+                ui.label("Active");
+                ui.selectable_value(&mut controls.active_weapon, None, "None");
+                for (ix, weapon) in controls.weapons.iter().enumerate() {
+                    ui.selectable_value(&mut controls.active_weapon, Some(ix), &weapon.name);
+                }
+            });
+            ui.separator();
+            let mut remove = None;
+            for (ix, weapon) in controls.weapons.iter_mut().enumerate() {
+                ui.push_id(ix, |ui| {
+                    egui::CollapsingHeader::new(&weapon.name)
+                        .id_source(ix)
+                        .show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.add_sized([100., 20.], egui::Label::new("Name"));
+                                ui.text_edit_singleline(&mut weapon.name);
+                            });
+                            selection_dropdown("Hotkey", &mut weapon.hotkey, ui);
+                            mode_dropdown(
+                                "Fire mode",
+                                &mut weapon.fire_mode,
+                                &common::FireMode::ALL,
+                                common::FireMode::label,
+                                ui,
+                            );
+                            egui::Grid::new("Weapon rates").show(ui, |ui| {
+                                ui.label("Rate");
+                                ui.add(
+                                    egui::Slider::new(&mut weapon.autofire_rpm, 60..=1800)
+                                        .step_by(10.)
+                                        .suffix(" rpm")
+                                        .integer(),
+                                );
+                                ui.end_row();
+                                ui.label("Duty");
+                                ui.add(
+                                    egui::Slider::new(&mut weapon.autofire_duty, 5..=95)
+                                        .step_by(5.)
+                                        .suffix(" % on")
+                                        .integer(),
+                                );
+                                ui.end_row();
+                                ui.label("Burst");
+                                ui.add(
+                                    egui::Slider::new(&mut weapon.burst_count, 1..=10)
+                                        .step_by(1.)
+                                        .suffix(" shot burst")
+                                        .integer(),
+                                );
+                                ui.end_row();
+                                ui.label("ADS sensitivity").on_hover_text(
+                                    "Only while this preset is active; without one, aiming uses the full rate",
+                                );
+                                ui.add(
+                                    egui::Slider::new(&mut weapon.ads_sensitivity, 10..=200)
+                                        .step_by(5.)
+                                        .suffix(" %")
+                                        .integer(),
+                                );
+                                ui.end_row();
+                            });
+                            recoil_pattern_editor(
+                                &mut weapon.recoil_pattern,
+                                &mut weapon.recoil_pattern_per_shot,
+                                ui,
+                            );
+                            if ui.button("\u{1f5d1} Delete preset").clicked() {
+                                remove = Some(ix);
+                            }
+                        });
+                });
+            }
+            if let Some(ix) = remove {
+                controls.weapons.remove(ix);
+                controls.active_weapon = match controls.active_weapon {
+                    Some(active) if active == ix => None,
+                    Some(active) if active > ix => Some(active - 1),
+                    active => active,
+                };
+            }
+            if ui.button("\u{2795} Add preset").clicked() {
+                controls.weapons.push(common::WeaponPreset::default());
+            }
+        });
+}
+
+fn chords_editor(chords: &mut Vec<common::Chord>, ui: &mut egui::Ui) {
+    let mut remove = None;
+    for (ix, chord) in chords.iter_mut().enumerate() {
+        ui.push_id(ix, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for (key_ix, key) in chord.keys.iter_mut().enumerate() {
+                    if key_ix > 0 {
+                        ui.label("+");
+                    }
+                    egui::ComboBox::from_id_source(("Key", key_ix))
+                        .width(90.)
+                        .selected_text(label_for_code(key))
+                        .show_ui(ui, |ui| {
+                            for (l, v) in KEYS {
+                                ui.selectable_value(key, *v, *l);
+                            }
+                        });
+                }
+                // Choosing None for a key takes it out of the chord.
+                chord.keys.retain(|key| *key != 0);
+                let mut added = 0;
+                egui::ComboBox::from_id_source("Add key")
+                    .width(30.)
+                    .selected_text("\u{2795}")
+                    .show_ui(ui, |ui| {
+                        for (l, v) in &KEYS[1..] {
+                            ui.selectable_value(&mut added, *v, *l);
+                        }
+                    });
+                if added != 0 {
+                    chord.keys.push(added);
+                }
+                ui.label("\u{27a1}");
+                let mut remove_target = None;
+                for (target_ix, target) in chord.targets.iter_mut().enumerate() {
+                    egui::ComboBox::from_id_source(("Target", target_ix))
+                        .width(90.)
+                        .selected_text(target.label())
+                        .show_ui(ui, |ui| {
+                            for option in common::BindingTarget::ALL {
+                                ui.selectable_value(target, option, option.label());
+                            }
+                            if ui.selectable_label(false, "None").clicked() {
+                                remove_target = Some(target_ix);
+                            }
+                        });
+                }
+                if let Some(target_ix) = remove_target {
+                    chord.targets.remove(target_ix);
+                }
+                let mut added = None;
+                egui::ComboBox::from_id_source("Add target")
+                    .width(30.)
+                    .selected_text("\u{2795}")
+                    .show_ui(ui, |ui| {
+                        for option in common::BindingTarget::ALL {
+                            ui.selectable_value(&mut added, Some(option), option.label());
+                        }
+                    });
+                if let Some(target) = added {
+                    chord.targets.push(target);
+                }
+                if ui.button("\u{274c}").on_hover_text("Remove").clicked() {
+                    remove = Some(ix);
+                }
+            });
+        });
+    }
+    if let Some(ix) = remove {
+        chords.remove(ix);
+    }
+    if ui.button("\u{2795} Add chord").clicked() {
+        chords.push(common::Chord::default());
+    }
+}
+
+fn layers_window(controls: &mut common::ButtonMapping, open: &mut bool, ctx: &egui::Context) {
+    egui::Window::new("Layers")
+        .open(open)
+        .resizable(false)
+        .vscroll(true)
+        .show(ctx, |ui| {
+            ui.label("While a layer is active its bindings replace the ones beneath it.");
+            ui.label("Keys left as None fall through; later layers take precedence.");
+            ui.label("Chords fire on their last key while the others are held, and win");
+            ui.label("over bindings with fewer keys. Several keys may drive one button.");
+            egui::CollapsingHeader::new("Base chords").show(ui, |ui| {
+                chords_editor(&mut controls.chords, ui);
+            });
+            let layers = &mut controls.layers;
+            let mut remove = None;
+            for (ix, layer) in layers.iter_mut().enumerate() {
+                ui.push_id(ix, |ui| {
+                    egui::CollapsingHeader::new(&layer.name)
+                        .id_source(ix)
+                        .show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.add_sized([100., 20.], egui::Label::new("Name"));
+                                ui.text_edit_singleline(&mut layer.name);
+                            });
+                            ui.horizontal(|ui| {
+                                selection_dropdown("Layer key", &mut layer.key, ui);
+                                ui.checkbox(&mut layer.toggle, "Toggle");
+                            });
+                            let bindings = &mut layer.bindings;
+                            for (label, code) in [
+                                ("Start", &mut bindings.start),
+                                ("Back", &mut bindings.back),
+                                ("DPad Up", &mut bindings.dpadu),
+                                ("DPad Down", &mut bindings.dpadd),
+                                ("DPad Left", &mut bindings.dpadl),
+                                ("DPad Right", &mut bindings.dpadr),
+                                ("A", &mut bindings.buttona),
+                                ("B", &mut bindings.buttonb),
+                                ("X", &mut bindings.buttonx),
+                                ("Y", &mut bindings.buttony),
+                                ("Left shoulder", &mut bindings.shoulderl),
+                                ("Right shoulder", &mut bindings.shoulderr),
+                                ("Left thumb", &mut bindings.lthumb),
+                                ("Right thumb", &mut bindings.rthumb),
+                                ("Stick Up", &mut bindings.lsticku),
+                                ("Stick Down", &mut bindings.lstickd),
+                                ("Stick Left", &mut bindings.lstickl),
+                                ("Stick Right", &mut bindings.lstickr),
+                                ("Walk", &mut bindings.walk),
+                            ] {
+                                selection_dropdown(label, code, ui);
+                            }
+                            ui.label("Chords");
+                            chords_editor(&mut layer.chords, ui);
+                            if ui.button("\u{1f5d1} Delete layer").clicked() {
+                                remove = Some(ix);
+                            }
+                        });
+                });
+            }
+            if let Some(ix) = remove {
+                layers.remove(ix);
+            }
+            if ui.button("\u{2795} Add layer").clicked() {
+                layers.push(common::Layer {
+                    name: "New layer".into(),
+                    key: 0,
+                    toggle: false,
+                    bindings: common::KeyBindings::default(),
+                    chords: vec![],
+                });
+            }
+        });
+}
+
+fn macro_step_editor(step: &mut common::MacroStep, ui: &mut egui::Ui) {
+    use common::MacroStep::*;
+    let (output, millis) = match *step {
+        Press { output } | Release { output } => (output, 50),
+        Hold { output, millis } => (output, millis),
+        Wait { millis } => (common::GamepadOutput::A, millis),
+    };
+    let kinds = [
+        ("Press", Press { output }),
+        ("Release", Release { output }),
+        ("Hold", Hold { output, millis }),
+        ("Wait", Wait { millis }),
+    ];
+    let current = kinds
+        .iter()
+        .position(|(_, k)| std::mem::discriminant(k) == std::mem::discriminant(step))
+        .unwrap_or(0);
+    egui::ComboBox::from_id_source("Step")
+        .width(70.)
+        .selected_text(kinds[current].0)
+        .show_ui(ui, |ui| {
+            for (label, kind) in kinds {
+                if ui
+                    .selectable_label(label == kinds[current].0, label)
+                    .clicked()
+                {
+                    *step = kind;
+                }
+            }
+        });
+    match step {
+        Press { output } | Release { output } | Hold { output, .. } => {
+            egui::ComboBox::from_id_source("Output")
+                .selected_text(output.label())
+                .show_ui(ui, |ui| {
+                    for option in common::GamepadOutput::ALL {
+                        ui.selectable_value(output, option, option.label());
+                    }
+                });
+        }
+        Wait { .. } => {}
+    }
+    match step {
+        Hold { millis, .. } | Wait { millis } => {
+            ui.add(
+                egui::DragValue::new(millis)
+                    .clamp_range(0..=10000)
+                    .suffix(" ms"),
+            );
+        }
+        Press { .. } | Release { .. } => {}
+    }
+}
+
+fn macros_window(macros: &mut Vec<common::Macro>, open: &mut bool, ctx: &egui::Context) {
+    egui::Window::new("Macros")
+        .open(open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.label("Press a macro's trigger to play it; press it again to cancel.");
+            let mut remove = None;
+            for (ix, m) in macros.iter_mut().enumerate() {
+                ui.push_id(ix, |ui| {
+                    egui::CollapsingHeader::new(&m.name)
+                        .id_source(ix)
+                        .show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.add_sized([100., 20.], egui::Label::new("Name"));
+                                ui.text_edit_singleline(&mut m.name);
+                            });
+                            selection_dropdown("Trigger", &mut m.trigger, ui);
+                            let mut remove_step = None;
+                            for (step_ix, step) in m.steps.iter_mut().enumerate() {
+                                ui.push_id(step_ix, |ui| {
+                                    ui.horizontal(|ui| {
+                                        ui.label(format!("{:>2}.", step_ix + 1));
+                                        macro_step_editor(step, ui);
+                                        if ui.button("\u{274c}").on_hover_text("Remove").clicked() {
+                                            remove_step = Some(step_ix);
+                                        }
+                                    });
+                                });
+                            }
+                            if let Some(step_ix) = remove_step {
+                                m.steps.remove(step_ix);
+                            }
+                            ui.horizontal(|ui| {
+                                if ui.button("\u{2795} Add step").clicked() {
+                                    m.steps.push(common::MacroStep::Wait { millis: 50 });
+                                }
+                                if ui.button("\u{1f5d1} Delete macro").clicked() {
+                                    remove = Some(ix);
+                                }
+                            });
+                        });
+                });
+            }
+            if let Some(ix) = remove {
+                macros.remove(ix);
+            }
+            if ui.button("\u{2795} Add macro").clicked() {
+                macros.push(common::Macro {
+                    name: "New macro".into(),
+                    trigger: 0,
+                    steps: vec![],
+                });
+            }
+        });
+}
+
+fn script_window(script: &mut String, error: &str, open: &mut bool, ctx: &egui::Context) {
+    egui::Window::new("Script")
+        .open(open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.label("A Rhai script, run for every key event and then for every report.");
+            ui.label("It can read `input` and `key`, change `report`, and keep values");
+            ui.label("in `state` between runs. Without input it only runs again while it");
+            ui.label("reads `input.time` or sets `state.tick` to true.");
+            egui::ScrollArea::vertical()
+                .max_height(400.)
+                .show(ui, |ui| {
+                    ui.add(
+                        egui::TextEdit::multiline(script)
+                            .code_editor()
+                            .desired_rows(20)
+                            .desired_width(400.),
+                    );
+                });
+            if !error.is_empty() {
+                ui.colored_label(egui::Color32::RED, error);
+            } else if !script.trim().is_empty() {
+                ui.label("Running.");
+            }
+        });
+}
+
+fn inheritance_window(
+    configuration: &mut common::Configuration,
+    ix: usize,
+    open: &mut bool,
+    ctx: &egui::Context,
+) {
+    let Some(parent) = configuration.parent_of(ix) else {
+        return;
+    };
+    let parent = configuration.games[parent].name.clone();
+    let fields = configuration.inheritance(ix);
+    let mut inherit = None;
+    egui::Window::new("Inheritance")
+        .open(open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.label(format!(
+                "Values this game does not override follow '{}'.",
+                parent
+            ));
+            egui::ScrollArea::vertical()
+                .max_height(400.)
+                .show(ui, |ui| {
+                    egui::Grid::new("Inherited fields")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for field in &fields {
+                                if field.overridden {
+                                    ui.strong(&field.name);
+                                    ui.label(&field.value);
+                                    if ui
+                                        .small_button("Inherit")
+                                        .on_hover_text(format!("Use the value from '{}'", parent))
+                                        .clicked()
+                                    {
+                                        inherit = Some(field.name.clone());
+                                    }
+                                } else {
+                                    ui.weak(&field.name);
+                                    ui.weak(&field.value);
+                                    ui.weak("inherited");
+                                }
+                                ui.end_row();
+                            }
+                        });
+                });
+        });
+    if let Some(field) = inherit {
+        if let Err(e) = configuration.inherit_field(ix, &field) {
+            exit_with_error(e);
+        }
+    }
+}
+
 fn game_selection_dropdown(
     label: &str,
     active_game_index: &mut usize,
@@ -54,15 +762,34 @@ fn game_selection_dropdown(
 impl eframe::App for SerfApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         _ = crate::CONTEXT.set(ctx.clone());
+
+        if self.last_reload_check.elapsed() >= RELOAD_POLL {
+            self.last_reload_check = Instant::now();
+            if self.watcher.changed() {
+                if self.dirty() {
+                    self.changed_on_disk = true;
+                } else {
+                    self.reload();
+                }
+            }
+        }
+        ctx.request_repaint_after(RELOAD_POLL);
         //let dark = egui::Visuals::dark();
         //ctx.set_visuals(egui::Visuals { ..dark });
 
+        // Before anything takes a copy of the games, as importing adds one.
+        self.sharing
+            .show(ctx, &mut self.configuration, &mut self.active_game_index);
+
         let games = self.configuration.games.clone();
         let active_game = &mut self.configuration.games[self.active_game_index];
 
         if let Ok(new_button_map) = self.rx.try_recv() {
             active_game.controls = new_button_map;
         }
+        while let Ok(error) = self.script_rx.try_recv() {
+            self.script_error = error;
+        }
 
         // On each update, send out the updated configuration to the controller backend.
         if self.previous != active_game.controls {
@@ -73,11 +800,14 @@ impl eframe::App for SerfApp {
                         "Could not find message sink for back end controller"
                     ));
                 }
-                let mut data = active_game.controls.clone();
+                let mut data = active_game
+                    .controls
+                    .encode()
+                    .expect("Button map should always serialise");
                 let copydata = COPYDATASTRUCT {
                     dwData: common::CopyTypes::ButtonMap as usize,
-                    cbData: std::mem::size_of::<common::ButtonMapping>() as u32,
-                    lpData: (&mut data) as *mut common::ButtonMapping as *mut std::ffi::c_void,
+                    cbData: data.len() as u32,
+                    lpData: data.as_mut_ptr() as *mut std::ffi::c_void,
                 };
                 let res = SendMessageA(
                     hwui,
@@ -92,7 +822,69 @@ impl eframe::App for SerfApp {
                 }
             }
             self.previous = active_game.controls.clone();
+            // Edits override what the game inherited, and pass down to the
+            // games inheriting from it.
+            self.configuration.note_overrides(self.active_game_index);
+            if let Err(e) = self.configuration.resolve() {
+                log::error!("Unable to resolve inherited controls: {:#}", e);
+            }
         }
+        if self.validated_games != self.configuration.games {
+            self.problems = common::validate(&self.configuration);
+            self.validated_games = self.configuration.games.clone();
+            self.save_error.clear();
+        }
+        self.diagnostics.show(ctx);
+        inheritance_window(
+            &mut self.configuration,
+            self.active_game_index,
+            &mut self.show_inheritance,
+            ctx,
+        );
+        recoil_pattern_window(
+            &mut self.configuration.games[self.active_game_index].controls,
+            &mut self.show_recoil_pattern,
+            ctx,
+        );
+        weapons_window(
+            &mut self.configuration.games[self.active_game_index].controls,
+            &mut self.show_weapons,
+            ctx,
+        );
+        layers_window(
+            &mut self.configuration.games[self.active_game_index].controls,
+            &mut self.show_layers,
+            ctx,
+        );
+        activation_window(
+            &mut self.configuration.games[self.active_game_index]
+                .controls
+                .activation,
+            &mut self.show_activation,
+            ctx,
+        );
+        macros_window(
+            &mut self.configuration.games[self.active_game_index]
+                .controls
+                .macros,
+            &mut self.show_macros,
+            ctx,
+        );
+        script_window(
+            &mut self.configuration.games[self.active_game_index]
+                .controls
+                .script,
+            &self.script_error,
+            &mut self.show_script,
+            ctx,
+        );
+        turbo_window(
+            &mut self.configuration.games[self.active_game_index]
+                .controls
+                .turbo,
+            &mut self.show_turbo,
+            ctx,
+        );
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.horizontal(|ui| {
                 game_selection_dropdown(
@@ -108,8 +900,8 @@ impl eframe::App for SerfApp {
                         .clicked()
                     {
                         self.configuration.games.push(common::Game {
-                            name: "New Game".into(),
-                            controls: common::ButtonMapping::default(),
+                            name: self.configuration.unused_name("New Game"),
+                            ..Default::default()
                         });
                         self.active_game_index = self.configuration.games.len() - 1;
                     }
@@ -118,11 +910,11 @@ impl eframe::App for SerfApp {
                         .on_hover_text("Delete")
                         .clicked()
                     {
-                        self.configuration.games.remove(self.active_game_index);
+                        self.configuration.remove_game(self.active_game_index);
                         if self.configuration.games.len() == 0 {
                             self.configuration.games.push(common::Game {
                                 name: "New Game".into(),
-                                controls: common::ButtonMapping::default(),
+                                ..Default::default()
                             });
                         }
                         self.active_game_index = 0;
@@ -130,32 +922,107 @@ impl eframe::App for SerfApp {
                 });
             });
             ui.horizontal(|ui| {
-                ui.add(
-                    egui::TextEdit::singleline(
-                        &mut self.configuration.games[self.active_game_index].name,
+                // Renamed through the configuration, so games inheriting from
+                // this one follow. Names another game has are refused, so what
+                // is typed is kept apart until it is free.
+                let id = ui.make_persistent_id("Game name");
+                let name = &self.configuration.games[self.active_game_index].name;
+                if !ui.memory().has_focus(id) {
+                    self.name_edit = name.clone();
+                }
+                if ui
+                    .add(
+                        egui::TextEdit::singleline(&mut self.name_edit)
+                            .id(id)
+                            .desired_width(290.),
                     )
-                    .desired_width(340.),
-                );
+                    .changed()
+                {
+                    _ = self
+                        .configuration
+                        .rename_game(self.active_game_index, self.name_edit.clone());
+                }
+                if self.name_edit != self.configuration.games[self.active_game_index].name {
+                    ui.colored_label(egui::Color32::RED, "\u{26a0} Name taken");
+                }
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                     if ui
                         .add_sized([40., 18.], egui::Button::new("\u{1f4be}"))
                         .on_hover_text("Save")
                         .clicked()
                     {
-                        self.configuration
-                            .save()
-                            .expect("Unable to write out configuration to disk");
+                        let errors = common::validate(&self.configuration)
+                            .iter()
+                            .filter(|d| d.severity == common::Severity::Error)
+                            .count();
+                        if errors > 0 {
+                            self.save_error = format!(
+                                "Not saved: {} {}, listed below",
+                                errors,
+                                if errors == 1 { "error" } else { "errors" }
+                            );
+                        } else if let Err(e) = self.configuration.save() {
+                            // Saving is atomic, so a failure leaves the file as it was.
+                            exit_with_error(e.context("Unable to write out configuration to disk"));
+                        } else {
+                            self.watcher.mark();
+                            self.saved_games = self.configuration.games.clone();
+                            self.changed_on_disk = false;
+                            self.save_error.clear();
+                        }
                     }
                     if ui
                         .add_sized([40., 18.], egui::Button::new("\u{2397}"))
                         .on_hover_text("Revert")
                         .clicked()
                     {
-                        self.configuration = common::Configuration::load()
-                            .expect("Unable to load old configuration from disk");
+                        self.reload();
+                    }
+                    if ui
+                        .add_sized([40., 18.], egui::Button::new("\u{1f4ca}"))
+                        .on_hover_text("Diagnostics")
+                        .clicked()
+                    {
+                        self.diagnostics.open = !self.diagnostics.open;
                     }
                 });
             });
+            ui.horizontal(|ui| {
+                let ix = self.active_game_index;
+                ui.add_sized([100., 20.], egui::Label::new("Inherits from"));
+                let current = self.configuration.games[ix].parent.clone();
+                let mut chosen = current.clone();
+                egui::ComboBox::from_id_source("Inherits from")
+                    .width(220.)
+                    .selected_text(current.as_deref().unwrap_or("Nothing"))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut chosen, None, "Nothing");
+                        for (other, game) in self.configuration.games.iter().enumerate() {
+                            if self.configuration.can_inherit(ix, other) {
+                                ui.selectable_value(
+                                    &mut chosen,
+                                    Some(game.name.clone()),
+                                    game.name.as_str(),
+                                );
+                            }
+                        }
+                    });
+                if chosen != current {
+                    if let Err(e) = self.configuration.set_parent(ix, chosen) {
+                        exit_with_error(e);
+                    }
+                }
+                if self.configuration.games[ix].parent.is_some() {
+                    let overridden = self.configuration.games[ix].overrides.len();
+                    if ui
+                        .button(format!("{} overridden\u{2026}", overridden))
+                        .on_hover_text("Which values are inherited and which are this game's own")
+                        .clicked()
+                    {
+                        self.show_inheritance = !self.show_inheritance;
+                    }
+                }
+            });
             ui.separator();
             ui.push_id("Shoulders", |ui| {
                 TableBuilder::new(ui)
@@ -369,6 +1236,41 @@ impl eframe::App for SerfApp {
                                 );
                             });
                         });
+                        body.row(20.0, |mut row| {
+                            row.col(|ui| {
+                                mode_dropdown(
+                                    "Left + Right",
+                                    &mut self.configuration.games[self.active_game_index]
+                                        .controls
+                                        .socd_horizontal,
+                                    &common::SocdMode::ALL,
+                                    common::SocdMode::label,
+                                    ui,
+                                );
+                            });
+                            row.col(|ui| {
+                                mode_dropdown(
+                                    "Up + Down",
+                                    &mut self.configuration.games[self.active_game_index]
+                                        .controls
+                                        .socd_vertical,
+                                    &common::SocdMode::ALL,
+                                    common::SocdMode::label,
+                                    ui,
+                                );
+                            });
+                        });
+                        body.row(20.0, |mut row| {
+                            row.col(|ui| {
+                                selection_dropdown(
+                                    "Walk",
+                                    &mut self.configuration.games[self.active_game_index]
+                                        .controls
+                                        .walk,
+                                    ui,
+                                );
+                            });
+                        });
                     });
             });
             ui.separator();
@@ -424,15 +1326,105 @@ impl eframe::App for SerfApp {
                                 );
                             });
                         });
+                        body.row(20.0, |mut row| {
+                            row.col(|ui| {
+                                ui.with_layout(
+                                    egui::Layout::right_to_left(egui::Align::TOP),
+                                    |ui| {
+                                        ui.label("Walk speed %");
+                                    },
+                                );
+                            });
+                            row.col(|ui| {
+                                ui.style_mut().spacing.slider_width = 288.;
+                                ui.add(
+                                    egui::Slider::new(
+                                        &mut self.configuration.games[self.active_game_index]
+                                            .controls
+                                            .walk_speed,
+                                        0..=100,
+                                    )
+                                    .step_by(1.)
+                                    .integer(),
+                                );
+                            });
+                        });
+                        body.row(20.0, |mut row| {
+                            row.col(|ui| {
+                                ui.with_layout(
+                                    egui::Layout::right_to_left(egui::Align::TOP),
+                                    |ui| {
+                                        ui.label("Ramp (ms)");
+                                    },
+                                );
+                            });
+                            row.col(|ui| {
+                                ui.style_mut().spacing.slider_width = 288.;
+                                ui.add(
+                                    egui::Slider::new(
+                                        &mut self.configuration.games[self.active_game_index]
+                                            .controls
+                                            .movement_ramp,
+                                        0..=500,
+                                    )
+                                    .step_by(10.)
+                                    .integer(),
+                                );
+                            });
+                        });
+                        body.row(20.0, |mut row| {
+                            row.col(|ui| {
+                                ui.with_layout(
+                                    egui::Layout::right_to_left(egui::Align::TOP),
+                                    |ui| {
+                                        ui.label("Stick gate");
+                                    },
+                                );
+                            });
+                            row.col(|ui| {
+                                let gate = &mut self.configuration.games[self.active_game_index]
+                                    .controls
+                                    .stick_gate;
+                                egui::ComboBox::from_id_source("Stick gate")
+                                    .selected_text(gate.label())
+                                    .show_ui(ui, |ui| {
+                                        for g in common::StickGate::ALL {
+                                            ui.selectable_value(gate, g, g.label());
+                                        }
+                                    });
+                            });
+                        });
                     });
             });
             ui.separator();
-            ui.checkbox(
-                &mut self.configuration.games[self.active_game_index]
-                    .controls
-                    .recoil_compensation_active,
-                "Recoil compensation (F8 toggles)",
-            );
+            ui.horizontal(|ui| {
+                ui.checkbox(
+                    &mut self.configuration.games[self.active_game_index]
+                        .controls
+                        .recoil_compensation_active,
+                    "Recoil compensation (F8 toggles)",
+                );
+                if ui
+                    .button("Pattern\u{2026}")
+                    .on_hover_text("Time-indexed recoil compensation")
+                    .clicked()
+                {
+                    self.show_recoil_pattern = !self.show_recoil_pattern;
+                }
+            });
+            ui.horizontal(|ui| {
+                let controls = &mut self.configuration.games[self.active_game_index].controls;
+                mode_dropdown(
+                    "Compensate",
+                    &mut controls.recoil_activation,
+                    &common::RecoilActivation::ALL,
+                    common::RecoilActivation::label,
+                    ui,
+                );
+                if controls.recoil_activation == common::RecoilActivation::WithModifier {
+                    selection_dropdown("Modifier", &mut controls.recoil_modifier, ui);
+                }
+            });
             ui.push_id("Recoil", |ui| {
                 TableBuilder::new(ui)
                     .column(Size::exact(100.))
@@ -540,24 +1532,232 @@ impl eframe::App for SerfApp {
                     .body(|mut body| {
                         body.row(20.0, |mut row| {
                             row.col(|ui| {
-                                ui.checkbox(
+                                mode_dropdown(
+                                    "Left (F5 cycles)",
                                     &mut self.configuration.games[self.active_game_index]
                                         .controls
-                                        .left_autofire,
-                                    "Left auto fire (F5 toggles)",
+                                        .left_fire_mode,
+                                    &common::FireMode::ALL,
+                                    common::FireMode::label,
+                                    ui,
                                 );
                             });
                             row.col(|ui| {
-                                ui.checkbox(
+                                mode_dropdown(
+                                    "Right (F6 cycles)",
                                     &mut self.configuration.games[self.active_game_index]
                                         .controls
-                                        .right_autofire,
-                                    "Right auto fire (F6 toggles)",
+                                        .right_fire_mode,
+                                    &common::FireMode::ALL,
+                                    common::FireMode::label,
+                                    ui,
+                                );
+                            });
+                        });
+                        body.row(20.0, |mut row| {
+                            row.col(|ui| {
+                                ui.style_mut().spacing.slider_width = 150.;
+                                ui.add(
+                                    egui::Slider::new(
+                                        &mut self.configuration.games[self.active_game_index]
+                                            .controls
+                                            .left_autofire_rpm,
+                                        60..=1200,
+                                    )
+                                    .step_by(10.)
+                                    .suffix(" rpm")
+                                    .integer(),
+                                );
+                            });
+                            row.col(|ui| {
+                                ui.style_mut().spacing.slider_width = 150.;
+                                ui.add(
+                                    egui::Slider::new(
+                                        &mut self.configuration.games[self.active_game_index]
+                                            .controls
+                                            .right_autofire_rpm,
+                                        60..=1200,
+                                    )
+                                    .step_by(10.)
+                                    .suffix(" rpm")
+                                    .integer(),
+                                );
+                            });
+                        });
+                        body.row(20.0, |mut row| {
+                            row.col(|ui| {
+                                ui.style_mut().spacing.slider_width = 150.;
+                                ui.add(
+                                    egui::Slider::new(
+                                        &mut self.configuration.games[self.active_game_index]
+                                            .controls
+                                            .left_autofire_duty,
+                                        5..=95,
+                                    )
+                                    .step_by(5.)
+                                    .suffix(" % on")
+                                    .integer(),
+                                );
+                            });
+                            row.col(|ui| {
+                                ui.style_mut().spacing.slider_width = 150.;
+                                ui.add(
+                                    egui::Slider::new(
+                                        &mut self.configuration.games[self.active_game_index]
+                                            .controls
+                                            .right_autofire_duty,
+                                        5..=95,
+                                    )
+                                    .step_by(5.)
+                                    .suffix(" % on")
+                                    .integer(),
+                                );
+                            });
+                        });
+                        body.row(20.0, |mut row| {
+                            row.col(|ui| {
+                                ui.style_mut().spacing.slider_width = 150.;
+                                ui.add(
+                                    egui::Slider::new(
+                                        &mut self.configuration.games[self.active_game_index]
+                                            .controls
+                                            .left_burst_count,
+                                        1..=10,
+                                    )
+                                    .step_by(1.)
+                                    .suffix(" shot burst")
+                                    .integer(),
+                                );
+                            });
+                            row.col(|ui| {
+                                ui.style_mut().spacing.slider_width = 150.;
+                                ui.add(
+                                    egui::Slider::new(
+                                        &mut self.configuration.games[self.active_game_index]
+                                            .controls
+                                            .right_burst_count,
+                                        1..=10,
+                                    )
+                                    .step_by(1.)
+                                    .suffix(" shot burst")
+                                    .integer(),
                                 );
                             });
                         });
                     });
             });
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Turbo buttons\u{2026}")
+                    .on_hover_text("Auto-repeat any button while its key is held")
+                    .clicked()
+                {
+                    self.show_turbo = !self.show_turbo;
+                }
+                if ui
+                    .button("Activation\u{2026}")
+                    .on_hover_text("Toggle, tap, long press and double tap bindings")
+                    .clicked()
+                {
+                    self.show_activation = !self.show_activation;
+                }
+                if ui
+                    .button("Layers\u{2026}")
+                    .on_hover_text(
+                        "Key chords, and alternate bindings while a layer key is held or toggled",
+                    )
+                    .clicked()
+                {
+                    self.show_layers = !self.show_layers;
+                }
+                if ui
+                    .button("Weapons\u{2026}")
+                    .on_hover_text("Per-weapon firing presets, switched by hotkey")
+                    .clicked()
+                {
+                    self.show_weapons = !self.show_weapons;
+                }
+                if ui
+                    .button("Macros\u{2026}")
+                    .on_hover_text("Timed sequences of gamepad actions")
+                    .clicked()
+                {
+                    self.show_macros = !self.show_macros;
+                }
+                let script = if self.script_error.is_empty() {
+                    egui::RichText::new("Script\u{2026}")
+                } else {
+                    egui::RichText::new("\u{26a0} Script\u{2026}").color(egui::Color32::RED)
+                };
+                if ui
+                    .button(script)
+                    .on_hover_text("Custom mapping logic for this game")
+                    .clicked()
+                {
+                    self.show_script = !self.show_script;
+                }
+                if ui
+                    .button("Share\u{2026}")
+                    .on_hover_text("Export this game as a profile, or import one")
+                    .clicked()
+                {
+                    self.sharing.open = !self.sharing.open;
+                }
+            });
+            let controls = &self.configuration.games[self.active_game_index].controls;
+            match controls
+                .active_weapon
+                .and_then(|ix| controls.weapons.get(ix))
+            {
+                Some(weapon) => ui.strong(format!("\u{1f52b} {}", weapon.name)),
+                None => ui.weak("No weapon preset"),
+            };
+            ui.weak(format!("\u{1f4c1} {}", self.configuration.path().display()))
+                .on_hover_text("Configuration file; set with --config or SERF_CONFIG");
+            if self.changed_on_disk {
+                ui.horizontal(|ui| {
+                    ui.colored_label(egui::Color32::YELLOW, "\u{26a0} Changed on disk");
+                    if ui
+                        .button("Reload")
+                        .on_hover_text("Load the file, losing the edits made here")
+                        .clicked()
+                    {
+                        self.reload();
+                    }
+                    if ui
+                        .button("Keep mine")
+                        .on_hover_text("Keep the edits made here; saving overwrites the file")
+                        .clicked()
+                    {
+                        self.changed_on_disk = false;
+                    }
+                });
+            }
+            if !self.reload_error.is_empty() {
+                ui.colored_label(
+                    egui::Color32::RED,
+                    format!("\u{26a0} Not reloaded: {}", self.reload_error),
+                );
+            }
+            if !self.save_error.is_empty() {
+                ui.colored_label(egui::Color32::RED, format!("\u{26a0} {}", self.save_error));
+            }
+            if !self.problems.is_empty() {
+                egui::CollapsingHeader::new(format!("\u{26a0} {} problems", self.problems.len()))
+                    .show(ui, |ui| {
+                        for problem in &self.problems {
+                            let colour = match problem.severity {
+                                common::Severity::Error => egui::Color32::RED,
+                                common::Severity::Warning => egui::Color32::YELLOW,
+                            };
+                            ui.colored_label(
+                                colour,
+                                format!("{}: {}", problem.path, problem.message),
+                            );
+                        }
+                    });
+            }
         });
     }
 }